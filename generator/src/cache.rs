@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+// remembers, for every input file, a hash of the files its packets were generated from,
+// so the inputs unchanged since the last run are not even parsed
+pub struct Cache {
+    path: PathBuf,
+    salt: String,
+    entries: HashMap<PathBuf, Entry>
+}
+
+struct Entry {
+    hash: u64,
+    files: Vec<PathBuf>,
+    outputs: Vec<PathBuf>,
+    // the type, class name and file name of its packets, for the packets embedding them
+    packets: Vec<(String, String, String)>
+}
+
+impl Cache {
+    // `salt` holds whatever besides the files changes the output, like the generator version and options
+    pub fn load(path: &Path, salt: String) -> Self {
+        let mut cache = Cache { path: path.to_owned(), salt, entries: HashMap::new() };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => {
+                debug!("No cache at {}", path.display());
+                return cache;
+            }
+        };
+        if let Err(line) = cache.parse(&contents) {
+            warn!("Ignoring the corrupted cache {} (line {:?})", path.display(), line);
+            cache.entries.clear();
+        }
+        cache
+    }
+
+    fn parse<'a>(&mut self, contents: &'a str) -> Result<(), &'a str> {
+        let mut input = None;
+        for line in contents.lines() {
+            let (key, value) = match line.find(' ') {
+                Some(idx) => (&line[..idx], &line[idx + 1..]),
+                None => return Err(line)
+            };
+            if key == "input" {
+                let path = PathBuf::from(value);
+                self.entries.insert(path.clone(), Entry { hash: 0, files: Vec::new(), outputs: Vec::new(), packets: Vec::new() });
+                input = Some(path);
+                continue;
+            }
+            let entry = match input {
+                Some(ref input) => self.entries.get_mut(input).unwrap(),
+                None => return Err(line)
+            };
+            match key {
+                "hash" => entry.hash = u64::from_str_radix(value, 16).map_err(|_| line)?,
+                "file" => entry.files.push(PathBuf::from(value)),
+                "output" => entry.outputs.push(PathBuf::from(value)),
+                "packet" => {
+                    let names = value.split(' ').collect::<Vec<_>>();
+                    match names[..] {
+                        [type_, class_name, file_name] => entry.packets.push((type_.to_owned(), class_name.to_owned(), file_name.to_owned())),
+                        _ => return Err(line)
+                    }
+                },
+                _ => return Err(line)
+            }
+        }
+        Ok(())
+    }
+
+    // an input is fresh when neither it nor its includes changed and its outputs are still around
+    pub fn is_fresh(&self, input: &Path) -> bool {
+        match self.entries.get(&canonical(input)) {
+            Some(entry) => entry.outputs.iter().all(|output| output.is_file())
+                && hash_files(&self.salt, &entry.files) == Some(entry.hash),
+            None => false
+        }
+    }
+
+    // the files an input was generated from, itself included, and the ones generated from it
+    pub fn entry(&self, input: &Path) -> Option<(Vec<PathBuf>, Vec<PathBuf>)> {
+        self.entries.get(&canonical(input)).map(|entry| (entry.files.clone(), entry.outputs.clone()))
+    }
+
+    pub fn packets(&self, input: &Path) -> Vec<(String, String, String)> {
+        self.entries.get(&canonical(input)).map(|entry| entry.packets.clone()).unwrap_or_default()
+    }
+
+    pub fn update(&mut self, input: &Path, dependencies: &[PathBuf], outputs: Vec<PathBuf>, mut packets: Vec<(String, String, String)>) {
+        packets.sort();
+        let input = canonical(input);
+        let mut files = vec![input.clone()];
+        files.extend(dependencies.iter().filter(|file| **file != input).cloned());
+        match hash_files(&self.salt, &files) {
+            Some(hash) => {
+                let outputs = outputs.iter().map(|output| canonical(output)).collect();
+                self.entries.insert(input, Entry { hash, files, outputs, packets });
+            },
+            None => { self.entries.remove(&input); }
+        }
+    }
+
+    pub fn remove(&mut self, input: &Path) {
+        self.entries.remove(&canonical(input));
+    }
+
+    pub fn save(&self) -> Result<(), ::failure::Error> {
+        let mut inputs = self.entries.keys().collect::<Vec<_>>();
+        inputs.sort();
+        let mut contents = String::new();
+        for input in inputs {
+            let entry = &self.entries[input];
+            contents += &format!("input {}\nhash {:x}\n", input.display(), entry.hash);
+            for file in &entry.files {
+                contents += &format!("file {}\n", file.display());
+            }
+            for output in &entry.outputs {
+                contents += &format!("output {}\n", output.display());
+            }
+            for (type_, class_name, file_name) in &entry.packets {
+                contents += &format!("packet {} {} {}\n", type_, class_name, file_name);
+            }
+        }
+        ::codegen::write_if_changed(&self.path, contents.as_bytes())
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+fn hash_files(salt: &str, files: &[PathBuf]) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    salt.hash(&mut hasher);
+    for file in files {
+        file.hash(&mut hasher);
+        fs::read(file).ok()?.hash(&mut hasher);
+    }
+    Some(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cache;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn dir(test: &str) -> PathBuf {
+        ::testing::temp_dir(&format!("cache_{}", test))
+    }
+
+    #[test]
+    fn freshness() {
+        let dir = dir("freshness");
+        let (input, include, output) = (dir.join("a.xml"), dir.join("b.xml"), dir.join("a.h"));
+        fs::write(&input, "a").unwrap();
+        fs::write(&include, "b").unwrap();
+        fs::write(&output, "out").unwrap();
+        let path = dir.join("cache");
+        let mut cache = Cache::load(&path, "v1".to_owned());
+        assert!(!cache.is_fresh(&input));
+        cache.update(&input, ::std::slice::from_ref(&include), vec![output.clone()], vec![("PAKCS_A".to_owned(), "CliA".to_owned(), "cli_a".to_owned())]);
+        cache.save().unwrap();
+
+        let cache = Cache::load(&path, "v1".to_owned());
+        assert!(cache.is_fresh(&input));
+        assert_eq!(cache.entry(&input), Some((vec![input.clone(), include.clone()], vec![output.clone()])));
+        assert_eq!(cache.packets(&input), vec![("PAKCS_A".to_owned(), "CliA".to_owned(), "cli_a".to_owned())]);
+        // other options, a changed include or a missing output all mean generating again
+        assert!(!Cache::load(&path, "v2".to_owned()).is_fresh(&input));
+        fs::write(&include, "changed").unwrap();
+        assert!(!cache.is_fresh(&input));
+        fs::write(&include, "b").unwrap();
+        assert!(cache.is_fresh(&input));
+        fs::remove_file(&output).unwrap();
+        assert!(!cache.is_fresh(&input));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted() {
+        let dir = dir("corrupted");
+        let input = dir.join("a.xml");
+        fs::write(&input, "a").unwrap();
+        let path = dir.join("cache");
+        fs::write(&path, format!("input {}\nhash nothex\n", input.display())).unwrap();
+        let cache = Cache::load(&path, String::new());
+        assert!(!cache.is_fresh(&input));
+        assert_eq!(cache.entry(&input), None);
+        fs::write(&path, "hash 0\n").unwrap();
+        assert_eq!(Cache::load(&path, String::new()).entry(&input), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
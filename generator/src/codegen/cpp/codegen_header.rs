@@ -1,20 +1,40 @@
 use ::flat_ast::*;
 use std::io::{Result, Write};
 use ::heck::*;
+use std::collections::HashSet;
+use super::{item_type, is_validated, scaled_type, dynamic_sizes, has_dynamic_size, checked_types, has_checks, is_defined_in, in_runtime_namespace};
 
 pub (crate) struct CodeHeaderGenerator<'a, W: Write + 'a> {
     writer: &'a mut ::writer::Writer<W>,
-    version: String
+    version: String,
+    shared: bool,
+    namespace: String,
+    input_hash: Option<u64>,
+    dynamic: HashSet<String>,
+    checked: HashSet<String>
 }
 
 impl<'a, W: Write> CodeHeaderGenerator<'a, W> {
     pub fn new(writer: &'a mut ::writer::Writer<W>, version: String) -> Self {
         Self {
             writer,
-            version
+            version,
+            shared: false,
+            namespace: super::DEFAULT_NAMESPACE.to_owned(),
+            input_hash: None,
+            dynamic: HashSet::new(),
+            checked: HashSet::new()
         }
     }
 
+    pub fn set_namespace(&mut self, namespace: String) {
+        self.namespace = namespace;
+    }
+
+    pub fn set_input_hash(&mut self, input_hash: u64) {
+        self.input_hash = Some(input_hash);
+    }
+
     fn indent(&mut self) {
         self.writer.indent();
     }
@@ -29,36 +49,13 @@ impl<'a, W: Write> CodeHeaderGenerator<'a, W> {
     }
 
     pub fn generate(&mut self, packet: &Packet) -> Result<()> {
-        cg!(self, "#pragma once\n");
-        let version = self.version.clone();
-        cg!(self, "/* Generated with IDL v{} */\n", version);
-        self.doc(packet.doc())?;
-        cg!(self);
-        cg!(self, r#"#include "packetfactory.h""#);
-        cg!(self);
-        cg!(self, "#ifndef JSON_USE_IMPLICIT_CONVERSIONS");
-        cg!(self, "#define JSON_USE_IMPLICIT_CONVERSIONS 0");
-        cg!(self, "#include \"json.hpp\"");
-        cg!(self, "#endif");
-        
-        for content in packet.contents() {
-            use self::PacketContent::*;
-            match content {
-                Include(ref inc, system) => { 
-                    if *system {
-                        cg!(self, r#"#include <{}>"#, inc);
-                    } else {
-                        cg!(self, r#"#include "{}""#, inc);
-                    }
-                },
-                _ => {}
-            };
-        }
+        self.dynamic = dynamic_sizes(packet);
+        self.checked = checked_types(packet);
+        self.preamble(packet)?;
 
         cg!(self);
-        cg!(self, r#"namespace RoseCommon {{
-namespace Packet {{
-"#);
+        let namespace = self.namespace.clone();
+        self.open_namespaces(&namespace)?;
         
         cg!(self, "class {} : public CRosePacket {{", packet.class_name());
         self.indent();
@@ -71,12 +68,21 @@ namespace Packet {{
         cg!(self, "{0}& operator=({0}&&) = default;", packet.class_name());
         cg!(self, "~{}() = default;", packet.class_name());
         cg!(self);
-        cg!(self, "static constexpr size_t size();");
+        let elements = packet.contents().iter().filter_map(|content| match content {
+            PacketContent::Element(ref elem) => Some(&**elem),
+            _ => None
+        });
+        if has_dynamic_size(elements, &self.dynamic) {
+            cg!(self, "size_t size() const;");
+        } else {
+            cg!(self, "static constexpr size_t size();");
+        }
         cg!(self);
 
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
+                Simple(ref simple) if simple.origin().is_some() => self.shared_type(simple.name(), simple.origin())?,
                 Simple(ref simple) => self.simple_type(simple)?,
                 _ => {}
             };
@@ -87,6 +93,7 @@ namespace Packet {{
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
+                Complex(ref complex) if complex.origin().is_some() => self.shared_type(complex.name(), complex.origin())?,
                 Complex(ref complex) => self.complex_type(complex)?,
                 _ => {}
             };
@@ -104,6 +111,13 @@ namespace Packet {{
                 _ => {}
             };
         }
+        let has_checks = has_checks(packet.contents().iter().filter_map(|content| match content {
+            PacketContent::Element(ref elem) => Some(&**elem),
+            _ => None
+        }), &self.checked);
+        if has_checks {
+            cg!(self, "const char* get_invalid_field() const;");
+        }
 
         cg!(self);
         cg!(self);
@@ -126,6 +140,9 @@ namespace Packet {{
                 _ => {}
             };
         }
+        if has_checks {
+            cg!(self, "const char* invalid_field = nullptr;");
+        }
 
         self.dedent();
         self.dedent();
@@ -135,7 +152,7 @@ namespace Packet {{
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
-                Simple(s) => {
+                Simple(s) if s.origin().is_none() => {
                     self.simple_type_to_json(packet.class_name(), s)?;
                     self.simple_type_from_json(packet.class_name(), s)?;
                 },
@@ -148,7 +165,7 @@ namespace Packet {{
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
-                Complex(c) => {
+                Complex(c) if c.origin().is_none() => {
                     self.complex_type_to_json(packet.class_name(), c)?;
                     self.complex_type_from_json(packet.class_name(), c)?;
                 },
@@ -159,7 +176,99 @@ namespace Packet {{
         self.packet_to_json(packet)?;
         cg!(self);
         self.packet_from_json(packet)?;
-        cg!(self, "}}\n}}");
+        self.close_namespaces(namespace.split("::").count())?;
+        Ok(())
+    }
+
+    // the shared types live in their own namespace, next to their json conversions so they are found by ADL
+    pub fn generate_types(&mut self, types: &Packet) -> Result<()> {
+        self.shared = true;
+        self.dynamic = dynamic_sizes(types);
+        self.checked = checked_types(types);
+        self.preamble(types)?;
+
+        cg!(self);
+        let namespace = format!("{}::{}", self.namespace, types.class_name());
+        self.open_namespaces(&namespace)?;
+
+        for content in types.contents().iter().filter(|content| !is_defined_in(content, types)) {
+            use self::PacketContent::*;
+            match content {
+                Simple(ref simple) => self.shared_type(simple.name(), simple.origin())?,
+                Complex(ref complex) => self.shared_type(complex.name(), complex.origin())?,
+                _ => {}
+            }
+        }
+
+        for content in types.contents().iter().filter(|content| is_defined_in(content, types)) {
+            if let PacketContent::Simple(ref simple) = content {
+                self.simple_type(simple)?;
+            }
+        }
+
+        cg!(self);
+
+        for content in types.contents().iter().filter(|content| is_defined_in(content, types)) {
+            if let PacketContent::Complex(ref complex) = content {
+                self.complex_type(complex)?;
+            }
+        }
+
+        for content in types.contents().iter().filter(|content| is_defined_in(content, types)) {
+            use self::PacketContent::*;
+            match content {
+                Simple(s) => self.simple_type_to_json(types.class_name(), s)?,
+                Complex(c) => self.complex_type_to_json(types.class_name(), c)?,
+                _ => {}
+            }
+        }
+        self.close_namespaces(namespace.split("::").count())?;
+        Ok(())
+    }
+
+    fn open_namespaces(&mut self, namespace: &str) -> Result<()> {
+        for name in namespace.split("::") {
+            cg!(self, "namespace {} {{", name);
+        }
+        cg!(self);
+        if !in_runtime_namespace(namespace) {
+            cg!(self, "using namespace {};", super::RUNTIME_NAMESPACE);
+            cg!(self);
+        }
+        Ok(())
+    }
+
+    fn close_namespaces(&mut self, count: usize) -> Result<()> {
+        cg!(self, "{}", vec!["}"; count].join("\n"));
+        Ok(())
+    }
+
+    fn preamble(&mut self, packet: &Packet) -> Result<()> {
+        cg!(self, "#pragma once\n");
+        let banner = ::codegen::banner(&self.version, self.input_hash);
+        cg!(self, "/* {} */\n", banner);
+        self.doc(packet.doc())?;
+        cg!(self);
+        cg!(self, r#"#include "packetfactory.h""#);
+        cg!(self);
+        cg!(self, "#ifndef JSON_USE_IMPLICIT_CONVERSIONS");
+        cg!(self, "#define JSON_USE_IMPLICIT_CONVERSIONS 0");
+        cg!(self, "#include \"json.hpp\"");
+        cg!(self, "#endif");
+        
+        for content in packet.contents() {
+            use self::PacketContent::*;
+            match content {
+                Include(ref inc, system) => { 
+                    if *system {
+                        cg!(self, r#"#include <{}>"#, inc);
+                    } else {
+                        cg!(self, r#"#include "{}""#, inc);
+                    }
+                },
+                _ => {}
+            };
+        }
         Ok(())
     }
 
@@ -218,20 +327,31 @@ namespace Packet {{
             cg!(self, "virtual bool read(CRoseReader&) override;");
             cg!(self, "virtual bool write(CRoseBasePolicy&) const override;");
             cg!(self);
-            cg!(self, "static constexpr size_t size();");
+            if self.dynamic.contains(complex.name()) {
+                cg!(self, "size_t size() const;");
+            } else {
+                cg!(self, "static constexpr size_t size();");
+            }
             cg!(self);
             match complex.content() {
                 Seq(ref s) => {
+                    let has_checks = has_checks(s.elements().iter(), &self.checked);
                     for elem in s.elements() {
                         self.elem_setter(elem, complex.name())?;
                         self.elem_getter(elem)?;
                     }
+                    if has_checks {
+                        cg!(self, "const char* get_invalid_field() const;");
+                    }
                     cg!(self);
                     cg!(self, "private:");
                     self.indent();
                     for elem in s.elements() {
                         self.element(elem)?;
                     }
+                    if has_checks {
+                        cg!(self, "const char* invalid_field = nullptr;");
+                    }
                     self.dedent();
                 },
                 Choice(ref c) => {
@@ -277,6 +397,11 @@ namespace Packet {{
         Ok(())
     }
 
+    fn shared_type(&mut self, name: &str, origin: &Option<Origin>) -> Result<()> {
+        cg!(self, "using {0} = {1}::{0};", name, origin.as_ref().unwrap().namespace);
+        Ok(())
+    }
+
     fn simple_type(&mut self, simple: &SimpleType) -> Result<()> {
         cg!(self);
         self.doc(simple.doc())?;
@@ -289,6 +414,9 @@ namespace Packet {{
     }
 
     fn element(&mut self, elem: &Element) -> Result<()> {
+        if elem.padding().is_some() {
+            return Ok(());
+        }
         self.doc(elem.doc())?;
 
         if let Some(bitset) = elem.bitset() {
@@ -301,14 +429,19 @@ namespace Packet {{
         let (type_, bits) = if let Some(ref o) = elem.occurs() {
             use ::flat_ast::Occurs::*;
             let type_ = match o {
-                Unbounded => format!("std::vector<{}>", elem.type_()),
-                Num(n) => format!("std::array<{}, {}>", elem.type_(), n)
+                Unbounded => format!("std::vector<{}>", item_type(elem, elem.type_())),
+                Num(n) => format!("std::array<{}, {}>", item_type(elem, elem.type_()), n)
             };
             (type_, "".to_string())
         } else {
             let bits = elem.bits().map_or_else(|| "".to_string(), |b| format!(" : {}", b));
             (elem.type_().to_owned(), bits)
         };
+        let type_ = if elem.optional() {
+            format!("std::optional<{}>", type_)
+        } else {
+            type_
+        };
         let default = match elem.init() {
             self::ElementInitValue::Default(d) => " = ".to_string() + d,
             _ => "".to_string()
@@ -318,13 +451,16 @@ namespace Packet {{
     }
 
     fn elem_setter(&mut self, elem: &Element, class: &str) -> Result<()> {
+        if elem.count_for().is_some() || is_validated(elem) || elem.padding().is_some() {
+            return Ok(());
+        }
         let reference = if elem.reference() { "&" } else { "" };
         use ::flat_ast::Occurs::*;
         let type_ = if let Some(ref o) = elem.occurs() {
             match o {
-                Unbounded => format!("std::vector<{}>", elem.type_()),
+                Unbounded => format!("std::vector<{}>", item_type(elem, elem.type_())),
                 Num(n) => {
-                    format!("std::array<{}, {}>", elem.type_(), n)
+                    format!("std::array<{}, {}>", item_type(elem, elem.type_()), n)
                 }
             }
         } else {
@@ -333,31 +469,52 @@ namespace Packet {{
         cg!(self, "{}& set_{}(const {}{});", class, elem.name(), type_, reference);
         if let Some(ref o) = elem.occurs() {
             match o {
-                Unbounded => { cg!(self, "{}& add_{}(const {}{});", class, elem.name(), elem.type_(), reference); },
-                Num(_) => { cg!(self, "{}& set_{}(const {}{}, size_t index);", class, elem.name(), elem.type_(), reference); }
+                Unbounded => { cg!(self, "{}& add_{}(const {}{});", class, elem.name(), item_type(elem, elem.type_()), reference); },
+                Num(_) => { cg!(self, "{}& set_{}(const {}{}, size_t index);", class, elem.name(), item_type(elem, elem.type_()), reference); }
             }
         }
+        if elem.optional() {
+            cg!(self, "{}& reset_{}();", class, elem.name());
+        }
+        if let Some(ref map) = elem.map() {
+            cg!(self, "{}& set_{}(const {} key, const {} value);", class, elem.name(), map.key, map.value);
+            cg!(self, "{}& erase_{}(const {} key);", class, elem.name(), map.key);
+        }
         Ok(())
     }
 
     fn elem_getter(&mut self, elem: &Element) -> Result<()> {
+        if elem.padding().is_some() {
+            return Ok(());
+        }
         let reference = if elem.reference() { "&" } else { "" };
         let type_ = if let Some(ref o) = elem.occurs() {
             use ::flat_ast::Occurs::*;
             match o {
-                Unbounded => format!("std::vector<{}>", elem.type_()),
+                Unbounded => format!("std::vector<{}>", item_type(elem, elem.type_())),
                 Num(n) => {
-                    format!("std::array<{}, {}>", elem.type_(), n)
+                    format!("std::array<{}, {}>", item_type(elem, elem.type_()), n)
                 }
             }
         } else {
             elem.type_().to_owned()
         };
+        let type_ = if elem.optional() {
+            format!("std::optional<{}>", type_)
+        } else {
+            type_
+        };
         let is_const = if elem.reference() { "const " } else { "" };
         cg!(self, "{}{}{} get_{}() const;", is_const, type_, reference, elem.name());
         let is_const = if elem.reference() { "const " } else { "" };
         if elem.occurs().is_some() {
-            cg!(self, "{}{}{} get_{}(size_t index) const;", is_const, elem.type_(), reference, elem.name());
+            cg!(self, "{}{}{} get_{}(size_t index) const;", is_const, item_type(elem, elem.type_()), reference, elem.name());
+        }
+        if let Some(ref embeds) = elem.embeds() {
+            cg!(self, "std::unique_ptr<{}> get_{}_packet() const;", embeds.class_name, elem.name());
+        }
+        if let Some(ref map) = elem.map() {
+            cg!(self, "std::optional<{}> get_{}(const {} key) const;", map.value, elem.name(), map.key);
         }
         Ok(())
     }
@@ -366,13 +523,13 @@ namespace Packet {{
         let args = packet.contents().iter().map(|elem| {
             use self::PacketContent::*;
             match elem {
-                Element(ref e) => match e.init() {
+                Element(ref e) if !e.optional() && e.count_for().is_none() => match e.init() {
                         self::ElementInitValue::Create => {
                             if let Some(ref o) = e.occurs() {
                                 use ::flat_ast::Occurs::*;
                                 let t = match o {
-                                    Unbounded => format!("std::vector<{}>", e.type_()),
-                                    Num(n) => format!("std::array<{}, {}>", e.type_(), n)
+                                    Unbounded => format!("std::vector<{}>", item_type(e, e.type_())),
+                                    Num(n) => format!("std::array<{}, {}>", item_type(e, e.type_()), n)
                                 };
                                 "const ".to_owned() + &t + &format!("& {}, ", e.name())
                             } else {
@@ -404,7 +561,51 @@ namespace Packet {{
         self.doc(restrict.doc())?;
         let base = restrict.base();
 
-        if is_enum {
+        if let Some(ref scale) = restrict.scale() {
+            let value = scaled_type(base);
+            cg!(self, "struct {} : public ISerialize {{", name);
+            self.indent();
+            cg!(self, "{}();", name);
+            cg!(self, "{}({});", name, value);
+            cg!(self, "{0}(const {0}&) = default;", name);
+            cg!(self, "{0}({0}&&) = default;", name);
+            cg!(self, "{0}& operator=(const {0}&) = default;", name);
+            cg!(self, "{0}& operator=({0}&&) = default;", name);
+            cg!(self, "virtual ~{}() = default;", name);
+            cg!(self);
+            cg!(self, "static constexpr size_t size();");
+            cg!(self);
+            cg!(self, "operator {0}() const {{ return static_cast<{0}>(raw * {1} + {2}); }}", value, scale.factor, scale.offset);
+            cg!(self, "{} get_raw() const {{ return raw; }}", base);
+            cg!(self, "bool isValid() const {{ return is_valid; }}");
+            cg!(self);
+            cg!(self, "virtual bool read(CRoseReader&) override;");
+            cg!(self, "virtual bool write(CRoseBasePolicy&) const override;");
+            cg!(self);
+            cg!(self, "private:");
+            self.indent();
+            cg!(self, "{} raw;", base);
+            cg!(self, "bool is_valid;");
+            self.dedent();
+        } else if is_enum && restrict.flags() {
+            cg!(self, "enum class {} : {} {{", name, base);
+            self.indent();
+            for content in restrict.contents() {
+                if let Enumeration(en) = content {
+                    self.doc(en.doc())?;
+                    cg!(self, "{} = {:#x},", en.value(), en.id());
+                }
+            }
+            self.dedent();
+            cg!(self, "}};");
+            // outside of a class the operators are plain functions of the namespace
+            let friend = if self.shared { "" } else { "friend " };
+            cg!(self, "{2}constexpr {0} operator|({0} lhs, {0} rhs) {{ return static_cast<{0}>(static_cast<{1}>(lhs) | static_cast<{1}>(rhs)); }}", name, base, friend);
+            cg!(self, "{2}constexpr {0} operator&({0} lhs, {0} rhs) {{ return static_cast<{0}>(static_cast<{1}>(lhs) & static_cast<{1}>(rhs)); }}", name, base, friend);
+            cg!(self, "{2}constexpr {0} operator~({0} value) {{ return static_cast<{0}>(~static_cast<{1}>(value)); }}", name, base, friend);
+            cg!(self, "{2}constexpr bool has({0} value, {0} flag) {{ return (static_cast<{1}>(value) & static_cast<{1}>(flag)) == static_cast<{1}>(flag); }}", name, base, friend);
+            return Ok(());
+        } else if is_enum {
             cg!(self, "enum {} : {} {{", name, base);
             self.indent();
             for content in restrict.contents() {
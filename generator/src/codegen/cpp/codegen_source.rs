@@ -1,19 +1,58 @@
 use ::flat_ast::*;
 use std::io::{Result, Write};
 use ::heck::*;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use super::{item_type, levels_type, is_validated, scaled_type, dynamic_sizes, has_dynamic_size, checked_types, has_checks, is_defined_in, in_runtime_namespace};
 
 pub (crate) struct CodeSourceGenerator<'a, W: Write + 'a> {
-    writer: &'a mut ::writer::Writer<W>
+    writer: &'a mut ::writer::Writer<W>,
+    flags: HashMap<String, String>,
+    namespace: String,
+    version: String,
+    input_hash: Option<u64>,
+    dynamic: HashSet<String>,
+    checked: HashSet<String>
 }
 
 impl<'a, W: Write> CodeSourceGenerator<'a, W> {
-    pub fn new(writer: &'a mut ::writer::Writer<W>) -> Self {
+    pub fn new(writer: &'a mut ::writer::Writer<W>, version: String) -> Self {
         Self {
-            writer
+            writer,
+            flags: HashMap::new(),
+            namespace: super::DEFAULT_NAMESPACE.to_owned(),
+            version,
+            input_hash: None,
+            dynamic: HashSet::new(),
+            checked: HashSet::new()
         }
     }
 
+    pub fn set_namespace(&mut self, namespace: String) {
+        self.namespace = namespace;
+    }
+
+    pub fn set_input_hash(&mut self, input_hash: u64) {
+        self.input_hash = Some(input_hash);
+    }
+
+    fn using_namespaces(&mut self) -> Result<()> {
+        let namespace = self.namespace.clone();
+        let names = namespace.split("::").collect::<Vec<_>>();
+        if !in_runtime_namespace(&namespace) {
+            cg!(self, "using namespace {};", super::RUNTIME_NAMESPACE);
+        }
+        for count in 1..=names.len() {
+            cg!(self, "using namespace {};", names[..count].join("::"));
+        }
+        Ok(())
+    }
+
+    fn banner(&mut self) -> Result<()> {
+        let banner = ::codegen::banner(&self.version, self.input_hash);
+        cg!(self, "/* {} */\n", banner);
+        Ok(())
+    }
+
     fn indent(&mut self) {
         self.writer.indent();
     }
@@ -28,43 +67,21 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
     }
 
     pub fn generate(&mut self, packet: &Packet) -> Result<()> {
+        self.banner()?;
         cg!(self, r#"#include "{}.h""#, packet.filename());
         cg!(self);
-        cg!(self, "using namespace RoseCommon;");
-        cg!(self, "using namespace RoseCommon::Packet;");
+        self.using_namespaces()?;
         cg!(self);
-
-        let iserialize = packet.contents().iter().filter_map(|elem| {
-            if PacketContent::is_type(elem) {
-                PacketContent::type_from_name(elem)
-            } else {
-                match elem {
-                    PacketContent::Element(ref e) => {
-                        match e.type_().as_ref() {
-                            "int8_t"
-                            | "uint8_t"
-                            | "int16_t"
-                            | "uint16_t"
-                            | "int32_t"
-                            | "uint32_t"
-                            | "int64_t"
-                            | "uint64_t"
-                            | "char"
-                            | "float"
-                            | "double"
-                            | "std::string" => None,
-                            _ => Some(e.type_().to_string())
-                        }
-                    },
-                    _ => None
-                }
-            }
-        }).collect::<::std::collections::HashSet<String>>();
+        self.compute_helpers(packet)?;
+        self.map_helpers(packet)?;
+        let iserialize = self.types(packet);
+        self.dynamic = dynamic_sizes(packet);
+        self.checked = checked_types(packet);
 
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
-                Simple(simple) => self.simple_type(simple, packet.class_name())?,
+                Simple(simple) if simple.origin().is_none() => self.simple_type(simple, packet.class_name())?,
                 _ => {}
             }
         }
@@ -74,14 +91,14 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
-                Complex(complex) => self.complex_type(complex, packet.class_name(), &iserialize)?,
+                Complex(complex) if complex.origin().is_none() => self.complex_type(complex, packet.class_name(), &iserialize)?,
                 _ => {}
             }
         }
 
         cg!(self);
         cg!(self, "{0}::{0}() : CRosePacket({0}::PACKET_ID) {{", packet.class_name());
-        if packet.class_name().starts_with("Srv") {
+        if packet.direction() == Direction::Server {
             self.indent();
             cg!(self, "set_server_packet();");
             self.dedent();
@@ -92,14 +109,29 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         cg!(self);
         cg!(self, "{0}::{0}(CRoseReader reader) : CRosePacket(reader) {{", packet.class_name());
         self.indent();
-        if packet.class_name().starts_with("Srv") {
+        if packet.direction() == Direction::Server {
             cg!(self, "set_server_packet();");
             cg!(self);
         }
+        if let Some(ref presence) = packet.presence() {
+            self.read_presence(presence, "return;")?;
+        }
+        let elements = packet_elements(packet);
+        let mut presence_bit = 0;
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
                 Element(elem) => {
+                    if elem.padding().is_some() {
+                        self.read_padding(elem, "return;")?;
+                        continue;
+                    }
+                    let guard = condition_expr(elem, &elements, packet.class_name(), "");
+                    self.open_condition(&guard)?;
+                    if elem.optional() {
+                        self.open_presence(elem, packet.presence(), presence_bit)?;
+                        presence_bit += 1;
+                    }
                     let base = if let Some(ref read_write) = elem.read_write() {
                         read_write.clone()
                     } else if let Some(ref enum_type) = elem.enum_type() {
@@ -110,9 +142,11 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                         clean_base(elem.type_())
                     };
 
-                    let name = elem.name().to_owned();
+                    let name = member(elem);
 
-                    if let Some(ref o) = elem.occurs() {
+                    if let Some(ref map) = elem.map() {
+                        self.read_map(map, &member(elem), elem.size_occurs().as_ref().unwrap(), "return;")?;
+                    } else if let Some(ref o) = elem.occurs() {
                         use ::flat_ast::Occurs::*;
                         match o {
                             Unbounded => {
@@ -128,25 +162,32 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                                 } else {
                                     "".to_owned()
                                 };
-                                if let Some(ref s) = elem.size_occurs() {
+                                let type_ = class_base + elem.type_();
+                                if let Some(ref count) = elem.occurs_ref().as_ref().or(elem.blob_length().as_ref()) {
+                                    cg!(self, "for (size_t index = 0; index < {}; ++index) {{", count);
+                                    self.indent();
+                                    cg!(self, "{} elem;", item_type(elem, &type_));
+                                    self.read_levels(elem, elem.inner_occurs(), &type_, "elem", &base, &enum_name, "return;", 1)?;
+                                    cg!(self, "{}.push_back(elem);", name);
+                                    self.dedent();
+                                    cg!(self, "}}");
+                                } else if let Some(ref s) = elem.size_occurs() {
                                     cg!(self, "{} size;", s);
                                     self.write_if_else(&format!("!reader.get_{}(size)", s), &[
                                         "return;"
                                     ], None)?;
                                     cg!(self, "while (size-- > 0) {{");
                                     self.indent();
-                                    cg!(self, "{} elem;", class_base + elem.type_());
-                                    self.write_if_else(&format!("!reader.get_{}({}elem)", base, enum_name), &[
-                                        "return;"
-                                    ], None)?;
-                                    cg!(self, "{}.push_back(elem);", elem.name());
+                                    cg!(self, "{} elem;", item_type(elem, &type_));
+                                    self.read_levels(elem, elem.inner_occurs(), &type_, "elem", &base, &enum_name, "return;", 1)?;
+                                    cg!(self, "{}.push_back(elem);", name);
                                     self.dedent();
                                     cg!(self, "}}");
                                 } else {
-                                    cg!(self, "{} elem;", class_base + elem.type_());
+                                    cg!(self, "{} elem;", type_);
                                     cg!(self, "while (reader.get_{}({}elem)) {{", base, enum_name);
                                     self.indent();
-                                    cg!(self, "{}.push_back(elem);", elem.name());
+                                    cg!(self, "{}.push_back(elem);", name);
                                     self.dedent();
                                     cg!(self, "}}");
                                 }
@@ -154,11 +195,14 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                                 cg!(self, "}}");
                             },
                             Num(n) => {
+                                let type_ = if elem.is_defined() {
+                                    packet.class_name().to_owned() + "::" + elem.type_()
+                                } else {
+                                    elem.type_().clone()
+                                };
                                 cg!(self, "for (size_t index = 0; index < {}; ++index) {{", n);
                                 self.indent();
-                                self.write_if_else(&format!("!reader.get_{}({}[index])", base, name), &[
-                                        "return;"
-                                    ], None)?;
+                                self.read_levels(elem, elem.inner_occurs(), &type_, &format!("{}[index]", name), &base, "", "return;", 1)?;
                                 self.dedent();
                                 cg!(self, "}}");
                             }
@@ -170,8 +214,20 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                         self.write_if_else(&format!("!reader.get_{}({})", enum_type, temp_name), &[
                             "return;"
                         ], None)?;
-                        cg!(self, "{} = static_cast<{}>({});", elem.name(), elem.type_(), temp_name);
+                        cg!(self, "{} = static_cast<{}>({});", name, elem.type_(), temp_name);
                         cg!(self);
+                    } else if let Some(flags) = self.flags.get(&elem.type_().to_lower_camel_case()).cloned() {
+                        let type_ = if elem.is_defined() {
+                            packet.class_name().to_owned() + "::" + elem.type_()
+                        } else {
+                            elem.type_().clone()
+                        };
+                        let temp_name = format!("{}_temp", elem.name());
+                        cg!(self, "{} {};", flags, temp_name);
+                        self.write_if_else(&format!("!reader.get_{}({})", flags, temp_name), &[
+                                "return;"
+                            ], None)?;
+                        cg!(self, "{} = static_cast<{}>({});", name, type_, temp_name);
                     } else {
                         let name = if let Some(bitset) = elem.bitset() {
                             if bitset.start == 0 {
@@ -180,7 +236,7 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                                 None
                             }
                         } else {
-                            Some(elem.name())
+                            Some(&name)
                         };
                         let base = if elem.bitset().is_some() {
                             "bitset".to_owned()
@@ -188,15 +244,20 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                             base
                         };
                         if let Some(name) = name {
-                            self.write_if_else(&format!("!reader.get_{}({})", base, name), &[
-                                    "return;"
-                                ], None)?;
+                            let fail = self.nested_fail(elem, name, "return;");
+                            self.write_if_else(&format!("!reader.get_{}({})", base, name), &fail.iter().map(String::as_str).collect::<Vec<_>>(), None)?;
                         }
                     }
+                    self.check_fixed(elem, "return;")?;
+                    if elem.optional() {
+                        self.close_presence()?;
+                    }
+                    self.close_condition(&guard)?;
                 },
                 _ => {}
             }
         }
+        self.check_computes(&elements, packet.class_name(), &iserialize, "return;")?;
         self.dedent();
         cg!(self, "}}");
         cg!(self);
@@ -211,59 +272,35 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                 _ => {}
             }
         }
+        if has_checks(packet_elements(packet).into_iter(), &self.checked) {
+            self.invalid_field_getter(packet.class_name())?;
+        }
 
         self.create(packet)?;
         cg!(self);
         self.pack(packet, &iserialize)?;
         cg!(self);
-        cg!(self, "constexpr size_t {}::size() {{", packet.class_name());
+        let elements = packet_elements(packet);
+        self.size_signature(packet.class_name(), has_dynamic_size(elements.iter().cloned(), &self.dynamic))?;
         self.indent();
         let iserialize = packet.contents().iter().filter_map(|elem| match elem {
             self::PacketContent::Element(elem) => if elem.is_defined() { Some(elem.type_().to_owned()) } else { None },
             _ => None
         }).collect::<::std::collections::HashSet<String>>();
         cg!(self, "size_t size = 0;");
-        for elem in packet.contents() {
-            match elem {
-                self::PacketContent::Element(elem) => {
-                    if elem.type_() == "std::string" {
-                        continue;
-                    }
-                    if let Some(ref size) = elem.size_occurs() {
-                        cg!(self, "size += sizeof({}); // {}", size, elem.name());
-                    }
-                    let rhs = if iserialize.contains(&elem.type_().to_owned().to_lower_camel_case()) && elem.enum_type().is_none() {
-                        format!("{}::size()", elem.type_())
-                    } else {
-                        let rhs = elem.bitset().as_ref().map_or(Some(format!("sizeof({})", elem.type_())), |bitset| if bitset.start == 0 {
-                            Some(format!("{} / 8", bitset.size))
-                        } else {
-                            None
-                        });
-                        
-                        if rhs.is_none() {
-                            continue;
-                        }
-                        rhs.unwrap()
-                    };
-                    let rhs = if let Some(ref o) = elem.occurs() {
-                        use ::flat_ast::Occurs::*;
-                        match o {
-                            Unbounded => rhs,
-                            Num(n) => rhs + " * " + n
-                        }
-                    } else {
-                        rhs
-                    };
-                    let name = if let Some(bitset) = elem.bitset() {
-                        &bitset.name
-                    } else {
-                        elem.name()
-                    };
-                    cg!(self, "size += {}; // {}", rhs, name);
-                },
-                _ => {}
+        if let Some(ref presence) = packet.presence() {
+            cg!(self, "size += sizeof({}); // presence", presence);
+        }
+        for elem in &elements {
+            if elem.type_() == "std::string" {
+                continue;
             }
+            let guard = condition_expr(elem, &elements, packet.class_name(), "");
+            self.open_condition(&guard)?;
+            self.open_optional(elem)?;
+            self.packet_elem_size(elem, &iserialize)?;
+            self.close_optional(elem)?;
+            self.close_condition(&guard)?;
         }
         cg!(self, "return size;");
         self.dedent();
@@ -274,7 +311,7 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
-                Simple(s) => {
+                Simple(s) if s.origin().is_none() => {
                     self.simple_type_to_json(packet.class_name(), s)?;
                     self.simple_type_from_json(packet.class_name(), s)?;
                 },
@@ -287,7 +324,7 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
-                Complex(c) => {
+                Complex(c) if c.origin().is_none() => {
                     self.complex_type_to_json(packet.class_name(), c)?;
                     self.complex_type_from_json(packet.class_name(), c)?;
                 },
@@ -301,25 +338,98 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         Ok(())
     }
 
+    pub fn generate_types(&mut self, types: &Packet) -> Result<()> {
+        self.banner()?;
+        cg!(self, r#"#include "{}.h""#, types.filename());
+        cg!(self);
+        self.using_namespaces()?;
+        cg!(self);
+        self.compute_helpers(types)?;
+        self.map_helpers(types)?;
+        let iserialize = self.types(types);
+        self.dynamic = dynamic_sizes(types);
+        self.checked = checked_types(types);
+        self.namespace = format!("{}::{}", self.namespace, types.class_name());
+
+        for content in types.contents().iter().filter(|content| is_defined_in(content, types)) {
+            if let PacketContent::Simple(simple) = content {
+                self.simple_type(simple, types.class_name())?;
+            }
+        }
+
+        cg!(self);
+
+        for content in types.contents().iter().filter(|content| is_defined_in(content, types)) {
+            if let PacketContent::Complex(complex) = content {
+                self.complex_type(complex, types.class_name(), &iserialize)?;
+            }
+        }
+
+        cg!(self);
+
+        for content in types.contents().iter().filter(|content| is_defined_in(content, types)) {
+            use self::PacketContent::*;
+            match content {
+                Simple(s) => self.simple_type_to_json(types.class_name(), s)?,
+                Complex(c) => self.complex_type_to_json(types.class_name(), c)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // remembers the flag-set enums and gives the types read and written through `ISerialize`
+    fn types(&mut self, packet: &Packet) -> HashSet<String> {
+        // flag-set enums are scoped, so they have to be converted to their base type explicitly
+        self.flags = packet.contents().iter().filter_map(|content| match content {
+            PacketContent::Simple(ref s) => s.contents().iter().find_map(|content| match content {
+                SimpleTypeContent::Restriction(ref r) if r.flags() => Some((s.name().clone(), r.base().clone())),
+                _ => None
+            }),
+            _ => None
+        }).collect();
+
+        packet.contents().iter().filter_map(|elem| {
+            if PacketContent::is_type(elem) {
+                PacketContent::type_from_name(elem)
+            } else {
+                match elem {
+                    PacketContent::Element(ref e) => {
+                        match e.type_().as_ref() {
+                            "int8_t"
+                            | "uint8_t"
+                            | "int16_t"
+                            | "uint16_t"
+                            | "int32_t"
+                            | "uint32_t"
+                            | "int64_t"
+                            | "uint64_t"
+                            | "char"
+                            | "float"
+                            | "double"
+                            | "std::string" => None,
+                            _ => Some(e.type_().to_string())
+                        }
+                    },
+                    _ => None
+                }
+            }
+        }).collect::<::std::collections::HashSet<String>>()
+    }
+
     fn packet_to_json(&mut self, packet: &Packet) -> Result<()> {
-        cg!(self, "void RoseCommon::Packet::to_json(nlohmann::json& j, const {}& data) {{", packet.class_name());
+        cg!(self, "void {}::to_json(nlohmann::json& j, const {}& data) {{", self.namespace, packet.class_name());
         self.indent();
         cg!(self, "j = nlohmann::json{{");
         self.indent();
         cg!(self, "{{ \"metadata\", {{ {{ \"packet\", \"{}\" }}, {{ \"size\", data.get_size() }} }} }},", packet.type_());
         cg!(self, "{{ \"fields\", {{");
         self.indent();
+        let elements = packet_elements(packet);
         for content in packet.contents() {
             use self::PacketContent::*;
             match content {
-                Element(e) => {
-                    let bitfield = if e.bitset().is_some() {
-                        " == 1"
-                    } else {
-                        ""
-                    };
-                    cg!(self, "{{ \"{0}\", data.get_{0}(){1} }},", e.name(), bitfield);
-                }
+                Element(e) => self.elem_to_json(e, condition_expr(e, &elements, packet.class_name(), "data."))?,
                 _ => {}
             }
         }
@@ -337,8 +447,24 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
     }
 
     fn simple_type_to_json(&mut self, packet_name:&str, element: &SimpleType) -> Result<()> {
-        cg!(self, "void RoseCommon::Packet::to_json(nlohmann::json& j, const {}::{}& data) {{", packet_name, element.name());
+        cg!(self, "void {}::to_json(nlohmann::json& j, const {}::{}& data) {{", self.namespace, packet_name, element.name());
         self.indent();
+        if self.flags.contains_key(element.name()) {
+            cg!(self, "j = nlohmann::json::array();");
+            for content in element.contents() {
+                let SimpleTypeContent::Restriction(res) = content;
+                for content in res.contents() {
+                    if let RestrictionContent::Enumeration(en) = content {
+                        self.write_if_else(&format!("has(data, {}::{}::{})", packet_name, element.name(), en.value()), &[
+                            &format!("j.push_back(\"{}\");", en.value())
+                        ], None)?;
+                    }
+                }
+            }
+            self.dedent();
+            cg!(self, "}}");
+            return Ok(());
+        }
         cg!(self, "j = nlohmann::json{{");
         self.indent();
         for content in element.contents() {
@@ -349,7 +475,9 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                         Enumeration(_) => true,
                         _ => false
                     }).is_some();
-                    if is_enum {
+                    if res.scale().is_some() {
+                        cg!(self, "{{ \"value\", static_cast<{}>(data) }},", scaled_type(res.base()));
+                    } else if is_enum {
                         cg!(self, "{{ \"value\", static_cast<{}>(data) }},", res.base());
                     } else {
                         cg!(self, "{{ \"value\", data.operator {}() }},", res.base());
@@ -372,20 +500,16 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         if element.inline() == true {
             return Ok(());
         }
-        cg!(self, "void RoseCommon::Packet::to_json(nlohmann::json& j, const {}::{}& data) {{", packet_name, element.name());
+        cg!(self, "void {}::to_json(nlohmann::json& j, const {}::{}& data) {{", self.namespace, packet_name, element.name());
         self.indent();
         use ::flat_ast::ComplexTypeContent::*;
         cg!(self, "j = nlohmann::json{{");
         self.indent();
         match element.content() {
             Seq(ref s) => {
+                let elements = s.elements().iter().collect::<Vec<_>>();
                 for elem in s.elements() {
-                    let bitfield = if elem.bitset().is_some() {
-                        " == 1"
-                    } else {
-                        ""
-                    };
-                    cg!(self, "{{ \"{0}\", data.get_{0}(){1} }},", elem.name(), bitfield);
+                    self.elem_to_json(elem, condition_expr(elem, &elements, packet_name, "data."))?;
                 }
             },
             Choice(ref c) => {
@@ -422,6 +546,36 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         Ok(())
     }
 
+    fn elem_to_json(&mut self, elem: &Element, guard: Option<String>) -> Result<()> {
+        if elem.padding().is_some() {
+            return Ok(());
+        }
+        if let Some(guard) = guard {
+            let value = if elem.map().is_some() {
+                format!("map_to_json(data.get_{}())", elem.name())
+            } else {
+                format!("nlohmann::json(data.get_{}())", elem.name())
+            };
+            cg!(self, "{{ \"{}\", {} ? {} : nlohmann::json(nullptr) }},", elem.name(), guard, value);
+            return Ok(());
+        }
+        if elem.optional() {
+            cg!(self, "{{ \"{0}\", data.get_{0}() ? nlohmann::json(data.get_{0}().value()) : nlohmann::json(nullptr) }},", elem.name());
+            return Ok(());
+        }
+        let bitfield = if elem.bitset().is_some() {
+            " == 1"
+        } else {
+            ""
+        };
+        if elem.map().is_some() {
+            cg!(self, "{{ \"{0}\", map_to_json(data.get_{0}()) }},", elem.name());
+            return Ok(());
+        }
+        cg!(self, "{{ \"{0}\", data.get_{0}(){1} }},", elem.name(), bitfield);
+        Ok(())
+    }
+
     fn complex_type(&mut self, complex: &ComplexType, class_name: &str, iserialize: &HashSet<String>) -> Result<()> {
         use ::flat_ast::ComplexTypeContent::*;
         let class_name = class_name.to_owned() + "::" + complex.name();
@@ -432,46 +586,31 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                         self.elem_setter(elem, &class_name, false)?;
                         self.elem_getter(elem, &class_name, false)?;
                     }
+                    if has_checks(s.elements().iter(), &self.checked) {
+                        self.invalid_field_getter(&class_name)?;
+                    }
                     self.pack_sequence(s, &class_name, iserialize)?;
                     cg!(self);
                     self.read_sequence(s, &class_name, iserialize)?;
                     cg!(self);
-                    cg!(self, "constexpr size_t {}::size() {{", class_name);
+                    let dynamic = self.dynamic.contains(complex.name());
+                    self.size_signature(&class_name, dynamic)?;
                     self.indent();
                     cg!(self, "size_t size = 0;");
+                    if let Some(ref presence) = s.presence() {
+                        cg!(self, "size += sizeof({}); // presence", presence);
+                    }
+                    let elements = s.elements().iter().collect::<Vec<_>>();
                     for elem in s.elements() {
                         if elem.type_() == "std::string" {
                             continue;
                         }
-                        let rhs = if iserialize.contains(&elem.type_().to_owned().to_lower_camel_case()) {
-                            format!("{}::size()", elem.type_())
-                        } else {
-                            let rhs = elem.bitset().as_ref().map_or(Some(format!("sizeof({})", elem.type_())), |bitset| if bitset.start == 0 {
-                                Some(format!("{} / 8", bitset.size))
-                            } else {
-                                None
-                            });
-                            
-                            if rhs.is_none() {
-                                continue;
-                            }
-                            rhs.unwrap()
-                        };
-                        let rhs = if let Some(ref o) = elem.occurs() {
-                            use ::flat_ast::Occurs::*;
-                            match o {
-                                Unbounded => rhs,
-                                Num(n) => rhs + " * " + n
-                            }
-                        } else {
-                            rhs
-                        };
-                        let name = if let Some(bitset) = elem.bitset() {
-                            &bitset.name
-                        } else {
-                            elem.name()
-                        };
-                        cg!(self, "size += {}; // {}", rhs, name);
+                        let guard = condition_expr(elem, &elements, class_name.split("::").next().unwrap(), "");
+                        self.open_condition(&guard)?;
+                        self.open_optional(elem)?;
+                        self.sequence_elem_size(elem, iserialize)?;
+                        self.close_optional(elem)?;
+                        self.close_condition(&guard)?;
                     }
                     cg!(self, "return size;");
                     self.dedent();
@@ -522,6 +661,9 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
     }
 
     fn elem_setter(&mut self, elem: &Element, class_name: &str, is_choice: bool) -> Result<()> {
+        if elem.count_for().is_some() || is_validated(elem) || elem.padding().is_some() {
+            return Ok(());
+        }
         let reference = if elem.reference() { "&" } else { "" };
         use ::flat_ast::Occurs::*;
         let type_base = if elem.is_defined() {
@@ -531,7 +673,7 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         };
         let type_ = if let Some(ref o) = elem.occurs() {
             match o {
-                Unbounded => format!("std::vector<{}>", type_base),
+                Unbounded => format!("std::vector<{}>", item_type(elem, &type_base)),
                 Num(n) => {
                     let n = if let Ok(_) = n.parse::<u32>() {
                         n.to_owned()
@@ -540,7 +682,7 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                     } else {
                         n.to_owned()
                     };
-                    format!("std::array<{}, {}>", type_base, n)
+                    format!("std::array<{}, {}>", item_type(elem, &type_base), n)
                 }
             }
         } else {
@@ -565,18 +707,28 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         if let Some(ref o) = elem.occurs() {
             match o {
                 Unbounded => {
-                    cg!(self, "{0}& {0}::add_{1}(const {2}{3} {1}) {{", class_name, elem.name(), elem.type_(), reference);
+                    cg!(self, "{0}& {0}::add_{1}(const {2}{3} {1}) {{", class_name, elem.name(), item_type(elem, elem.type_()), reference);
                     self.indent();
-                    cg!(self, "this->{0}.emplace_back({0});", elem.name());
+                    if elem.optional() {
+                        self.write_if_else(&format!("!this->{}.has_value()", elem.name()), &[
+                            &format!("this->{}.emplace();", elem.name())
+                        ], None)?;
+                    }
+                    cg!(self, "this->{}.emplace_back({});", member(elem), elem.name());
                     cg!(self, "return *this;");
                     self.dedent();
                     cg!(self, "}}");
                     cg!(self);
                 },
                 Num(_) => {
-                    cg!(self, "{0}& {0}::set_{1}(const {2}{3} {1}, size_t index) {{", class_name, elem.name(), elem.type_(), reference);
+                    cg!(self, "{0}& {0}::set_{1}(const {2}{3} {1}, size_t index) {{", class_name, elem.name(), item_type(elem, elem.type_()), reference);
                     self.indent();
-                    cg!(self, "this->{0}[index] = {0};", elem.name());
+                    if elem.optional() {
+                        self.write_if_else(&format!("!this->{}.has_value()", elem.name()), &[
+                            &format!("this->{}.emplace();", elem.name())
+                        ], None)?;
+                    }
+                    cg!(self, "this->{}[index] = {};", member(elem), elem.name());
                     cg!(self, "return *this;");
                     self.dedent();
                     cg!(self, "}}");
@@ -584,10 +736,38 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                 }
             }
         }      
+        if elem.optional() {
+            cg!(self, "{0}& {0}::reset_{1}() {{", class_name, elem.name());
+            self.indent();
+            cg!(self, "this->{}.reset();", elem.name());
+            cg!(self, "return *this;");
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+        }
+        if let Some(ref map) = elem.map() {
+            cg!(self, "{0}& {0}::set_{1}(const {2} key, const {3} value) {{", class_name, elem.name(), map.key, map.value);
+            self.indent();
+            cg!(self, "this->{}[key] = value;", member(elem));
+            cg!(self, "return *this;");
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+            cg!(self, "{0}& {0}::erase_{1}(const {2} key) {{", class_name, elem.name(), map.key);
+            self.indent();
+            cg!(self, "this->{}.erase(key);", member(elem));
+            cg!(self, "return *this;");
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+        }
         Ok(())
     }
 
     fn elem_getter(&mut self, elem: &Element, class_name: &str, is_choice: bool) -> Result<()> {
+        if elem.padding().is_some() {
+            return Ok(());
+        }
         use ::flat_ast::Occurs::*;
         let reference = if elem.reference() { "&" } else { "" };
         let type_base = if elem.is_defined() {
@@ -597,7 +777,7 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         };
         let type_ = if let Some(ref o) = elem.occurs() {
             match o {
-                Unbounded => format!("std::vector<{}>", type_base),
+                Unbounded => format!("std::vector<{}>", item_type(elem, &type_base)),
                 Num(n) => {
                     let n = if let Ok(_) = n.parse::<u32>() {
                         n.to_owned()
@@ -606,12 +786,17 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                     } else {
                         n.to_owned()
                     };
-                    format!("std::array<{}, {}>", type_base, n)
+                    format!("std::array<{}, {}>", item_type(elem, &type_base), n)
                 }
             }
         } else {
             type_base.clone()
         };
+        let type_ = if elem.optional() {
+            format!("std::optional<{}>", type_)
+        } else {
+            type_
+        };
         let is_const = if elem.reference() { "const " } else { "" };
         cg!(self, "{4}{2}{3} {0}::get_{1}() const {{", class_name, elem.name(), type_, reference, is_const);
         self.indent();
@@ -624,6 +809,8 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
             self.dedent();
             cg!(self, "}}");
             cg!(self, "return {}_tmp;", elem.name());
+        } else if let Some(ref vector) = elem.count_for() {
+            cg!(self, "return static_cast<{}>({}.size());", elem.type_(), vector);
         } else {
             cg!(self, "return {1}{0};", elem.name(), if is_choice { "data." } else { "" });
         }
@@ -632,13 +819,47 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         cg!(self);
         let is_const = if elem.reference() { "const " } else { "" };
         if elem.occurs().is_some() {
-            cg!(self, "{}{}{} {}::get_{}(size_t index) const {{", is_const, type_base, reference, class_name, elem.name());
+            cg!(self, "{}{}{} {}::get_{}(size_t index) const {{", is_const, item_type(elem, &type_base), reference, class_name, elem.name());
             self.indent();
-            cg!(self, "return {}[index];", elem.name());
+            cg!(self, "return {}[index];", member(elem));
             self.dedent();
             cg!(self, "}}");
             cg!(self);
         }
+        if let Some(ref map) = elem.map() {
+            cg!(self, "std::optional<{}> {}::get_{}(const {} key) const {{", map.value, class_name, elem.name(), map.key);
+            self.indent();
+            cg!(self, "const auto it = {}.find(key);", member(elem));
+            self.write_if_else(&format!("it == {}.end()", member(elem)), &["return std::nullopt;"], None)?;
+            cg!(self, "return it->second;");
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+        }
+        if let Some(ref embeds) = elem.embeds() {
+            // the embedded packet is only parsed when asked for, and only if the blob holds all of it
+            let embedded = &embeds.class_name;
+            cg!(self, "std::unique_ptr<{}> {}::get_{}_packet() const {{", embedded, class_name, elem.name());
+            self.indent();
+            let blob = member(elem);
+            self.write_if_else(&format!("{0}.size() < sizeof(uint16_t) || CRosePacket::size({0}.data()) > {0}.size()", blob), &[
+                "return nullptr;"
+            ], None)?;
+            cg!(self, "return {}::allocate({}.data());", embedded, blob);
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+        }
+        Ok(())
+    }
+
+    fn invalid_field_getter(&mut self, class_name: &str) -> Result<()> {
+        cg!(self, "const char* {}::get_invalid_field() const {{", class_name);
+        self.indent();
+        cg!(self, "return invalid_field;");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self);
         Ok(())
     }
 
@@ -646,7 +867,7 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         let args = packet.contents().iter().map(|elem| {
             use self::PacketContent::*;
             match elem {
-                Element(ref e) => match e.init() {
+                Element(ref e) if !e.optional() && e.count_for().is_none() => match e.init() {
                     self::ElementInitValue::Create => {
                         let base = if e.is_defined() {
                             packet.class_name().to_owned() + "::"
@@ -655,12 +876,8 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                         };
                         if let Some(ref o) = e.occurs() {
                                 use ::flat_ast::Occurs::*;
-                                let mut use_base = true;
                                 let t = match o {
-                                    Unbounded => {
-                                        use_base = false;
-                                        format!("std::vector<{}>", e.type_())
-                                    },
+                                    Unbounded => format!("std::vector<{}>", item_type(e, e.type_())),
                                     Num(n) => {
                                         let n = if let Ok(_) = n.parse::<u32>() {
                                             n.to_owned()
@@ -669,15 +886,10 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                                         } else {
                                             n.to_owned()
                                         };
-                                        format!("std::array<{}, {}>", e.type_(), n)
+                                        format!("std::array<{}, {}>", item_type(e, &(base.clone() + e.type_())), n)
                                     }
                                 };
-
-                                if use_base {
-                                    "const ".to_owned() + &base + &t + &format!("& {}, ", e.name())
-                                } else {
-                                    "const ".to_owned() + &t + &format!("& {}, ", e.name())
-                                }
+                                "const ".to_owned() + &t + &format!("& {}, ", e.name())
                             } else {
                                 "const ".to_owned() + &base + e.type_() + &format!("& {}, ", e.name())
                             }
@@ -697,7 +909,7 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         cg!(self, "{} packet;", packet.class_name());
         for content in packet.contents() {
             match content {
-                self::PacketContent::Element(ref e) => match e.init() {
+                self::PacketContent::Element(ref e) if !e.optional() && e.count_for().is_none() => match e.init() {
                     self::ElementInitValue::Create => { cg!(self, "packet.set_{}({});", e.name(), e.name()); },
                     _ => {}
                 },
@@ -731,61 +943,16 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
             cg!(self, "bool {}::pack(CRoseBasePolicy& writer) const {{", packet.class_name());
         }
         self.indent();
-        for content in packet.contents() {
-            use self::PacketContent::*;
-            match content {
-                Element(elem) => {
-                    let base = if let Some(ref read_write) = elem.read_write() {
-                        read_write.clone()
-                    } else if let Some(ref enum_type) = elem.enum_type() {
-                        enum_type.clone()
-                    } else if iserialize.contains(&elem.type_().to_owned().to_lower_camel_case()) {
-                        "iserialize".to_owned()
-                    } else {
-                        clean_base(elem.type_())
-                    };
-                    if let Some(ref o) = elem.occurs() {
-                        use ::flat_ast::Occurs::*;
-                        match o {
-                            Unbounded | Num(_) => {
-                                if let Some(ref s) = elem.size_occurs() {
-                                    self.write_if_else(&format!("!writer.set_{}({}.size())", s, elem.name()), &[
-                                        "return false;"
-                                    ], None)?;
-                                }
-                                cg!(self, "for (const auto& elem : {}) {{", elem.name());
-                                self.indent();
-                                self.write_if_else(&format!("!writer.set_{}(elem)", base), &[
-                                        "return false;"
-                                    ], None)?;
-                                self.dedent();
-                                cg!(self, "}}");
-                            }
-                        }
-                    } else {
-                        let name = if let Some(bitset) = elem.bitset() {
-                            if bitset.start == 0 {
-                                Some(&bitset.name)
-                            } else {
-                                None
-                            }
-                        } else {
-                            Some(elem.name())
-                        };
-                        let base = if elem.bitset().is_some() {
-                            "bitset".to_owned()
-                        } else {
-                            base
-                        };
-                        if let Some(name) = name {
-                            self.write_if_else(&format!("!writer.set_{}({})", base, name), &[
-                                    "return false;"
-                                ], None)?;
-                        }
-                    }
-                },
-                _ => {}
-            }
+        if let Some(ref presence) = packet.presence() {
+            let elements = packet.contents().iter().filter_map(|content| match content {
+                PacketContent::Element(elem) => Some(&**elem),
+                _ => None
+            });
+            self.write_presence(presence, elements, "return false;")?;
+        }
+        let elements = packet_elements(packet);
+        for elem in &elements {
+            self.write_element(elem, &elements, packet.class_name(), iserialize, true)?;
         }
         cg!(self, "return true;");
         self.dedent();
@@ -796,73 +963,136 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
     fn pack_sequence(&mut self, packet: &Sequence, class_name: &str, iserialize: &HashSet<String>) -> Result<()> {
         cg!(self, "bool {}::write(CRoseBasePolicy& writer) const {{", class_name);
         self.indent();
+        if let Some(ref presence) = packet.presence() {
+            self.write_presence(presence, packet.elements().iter(), "return false;")?;
+        }
+        let elements = packet.elements().iter().collect::<Vec<_>>();
         for elem in packet.elements() {
-            let base = if let Some(ref enum_type) = elem.enum_type() {
-                enum_type.clone()
-            } else if iserialize.contains(&elem.type_().to_owned().to_lower_camel_case()) {
-                "iserialize".to_owned()
-            } else {
-                clean_base(elem.type_())
-            };
-            if let Some(ref o) = elem.occurs() {
-                use ::flat_ast::Occurs::*;
-                match o {
-                    Unbounded => {
-                        if let Some(ref s) = elem.size_occurs() {
-                            self.write_if_else(&format!("!writer.set_{}({}.size())", s, elem.name()), &[
-                                "return false;"
-                            ], None)?;
-                        }
-                        cg!(self, "for (const auto& elem : {}) {{", elem.name());
-                        self.indent();
-                        self.write_if_else(&format!("!writer.set_{}(elem)", base), &[
-                                "return false;"
-                            ], None)?;
-                        self.dedent();
-                        cg!(self, "}}");
-                    },
-                    Num(n) => {
-                        cg!(self, "for (size_t index = 0; index < {}; ++index) {{", n);
-                        self.indent();
-                        self.write_if_else(&format!("!writer.set_{}({}[index])", base, elem.name()), &[
-                                "return false;"
-                            ], None)?;
-                        self.dedent();
-                        cg!(self, "}}");
+            self.write_element(elem, &elements, class_name, iserialize, true)?;
+        }
+        cg!(self, "return true;");
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    // `recompute` computes the computed fields from their range instead of writing their current value
+    fn write_element(&mut self, elem: &Element, elements: &[&Element], class_name: &str, iserialize: &HashSet<String>, recompute: bool) -> Result<()> {
+        if let Some(ref fill) = elem.padding() {
+            return self.write_padding(elem, fill, "return false;");
+        }
+        let guard = condition_expr(elem, elements, class_name.split("::").next().unwrap(), "");
+        self.open_condition(&guard)?;
+        if elem.optional() {
+            cg!(self, "if ({}.has_value()) {{", elem.name());
+            self.indent();
+        }
+        let value = member(elem);
+        let base = if let Some(ref read_write) = elem.read_write() {
+            read_write.clone()
+        } else if let Some(ref enum_type) = elem.enum_type() {
+            enum_type.clone()
+        } else if iserialize.contains(&elem.type_().to_owned().to_lower_camel_case()) {
+            "iserialize".to_owned()
+        } else {
+            clean_base(elem.type_())
+        };
+        if let Some(ref map) = elem.map() {
+            self.write_map(map, &member(elem), elem.size_occurs().as_ref().unwrap(), "return false;")?;
+        } else if let Some(ref o) = elem.occurs() {
+            use ::flat_ast::Occurs::*;
+            match o {
+                Unbounded => {
+                    if let Some(ref s) = elem.size_occurs() {
+                        self.write_if_else(&format!("!writer.set_{}({}.size())", s, value), &[
+                            "return false;"
+                        ], None)?;
+                    } else if let Some(ref length) = elem.blob_length() {
+                        self.write_if_else(&format!("{}.size() != {}", value, length), &[
+                            "return false;"
+                        ], None)?;
                     }
+                    cg!(self, "for (const auto& elem : {}) {{", value);
+                    self.indent();
+                    self.write_levels(elem.inner_occurs(), "elem", &base, "return false;", 1)?;
+                    self.dedent();
+                    cg!(self, "}}");
+                },
+                Num(n) => {
+                    cg!(self, "for (size_t index = 0; index < {}; ++index) {{", n);
+                    self.indent();
+                    self.write_levels(elem.inner_occurs(), &format!("{}[index]", value), &base, "return false;", 1)?;
+                    self.dedent();
+                    cg!(self, "}}");
                 }
-            } else {
-                let name = if let Some(bitset) = elem.bitset() {
-                    if bitset.start == 0 {
-                        Some(&bitset.name)
-                    } else {
-                        None
-                    }
-                } else {
-                    Some(elem.name())
-                };
-                let base = if elem.bitset().is_some() {
-                    "bitset".to_owned()
+            }
+        } else {
+            let name = if let Some(bitset) = elem.bitset() {
+                if bitset.start == 0 {
+                    Some(&bitset.name)
                 } else {
-                    base
-                };
-                if let Some(name) = name {
-                    self.write_if_else(&format!("!writer.set_{}({})", base, name), &[
-                            "return false;"
-                        ], None)?;
+                    None
                 }
+            } else {
+                Some(&value)
+            };
+            let base = if elem.bitset().is_some() {
+                "bitset".to_owned()
+            } else {
+                base
+            };
+            if let Some(ref vector) = elem.count_for() {
+                self.write_if_else(&format!("!writer.set_{}(static_cast<{}>({}.size()))", base, elem.type_(), vector), &[
+                        "return false;"
+                    ], None)?;
+            } else if elem.compute().is_some() && recompute {
+                self.write_compute(elem, elements, class_name, iserialize, &base)?;
+            } else if let Some(ref fixed) = elem.fixed() {
+                self.write_if_else(&format!("!writer.set_{}({})", base, fixed), &[
+                        "return false;"
+                    ], None)?;
+            } else if let Some(flags) = self.flags.get(&elem.type_().to_lower_camel_case()).cloned() {
+                self.write_if_else(&format!("!writer.set_{0}(static_cast<{0}>({1}))", flags, value), &[
+                        "return false;"
+                    ], None)?;
+            } else if let Some(name) = name {
+                self.write_if_else(&format!("!writer.set_{}({})", base, name), &[
+                        "return false;"
+                    ], None)?;
             }
         }
-        cg!(self, "return true;");
-        self.dedent();
-        cg!(self, "}}");
+        if elem.optional() {
+            self.close_presence()?;
+        }
+        self.close_condition(&guard)?;
         Ok(())
     }
 
     fn read_sequence(&mut self, packet: &Sequence, class_name: &str, iserialize: &HashSet<String>) -> Result<()> {
         cg!(self, "bool {}::read(CRoseReader& reader) {{", class_name);
         self.indent();
+        if let Some(ref presence) = packet.presence() {
+            self.read_presence(presence, "return false;")?;
+        }
+        let elements = packet.elements().iter().collect::<Vec<_>>();
+        let mut presence_bit = 0;
         for elem in packet.elements() {
+            if elem.padding().is_some() {
+                self.read_padding(elem, "return false;")?;
+                continue;
+            }
+            let guard = condition_expr(elem, &elements, class_name.split("::").next().unwrap(), "");
+            self.open_condition(&guard)?;
+            if elem.optional() {
+                self.open_presence(elem, packet.presence(), presence_bit)?;
+                presence_bit += 1;
+            }
+            let value = member(elem);
+            let type_ = if elem.is_defined() {
+                class_name.split("::").collect::<Vec<_>>()[0].to_owned() + "::" + elem.type_()
+            } else {
+                elem.type_().clone()
+            };
             let base = if let Some(ref enum_type) = elem.enum_type() {
                 enum_type.clone()
             } else if iserialize.contains(&elem.type_().to_owned().to_lower_camel_case()) {
@@ -870,29 +1100,57 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
             } else {
                 clean_base(elem.type_())
             };
-            if let Some(ref o) = elem.occurs() {
+            if let Some(ref map) = elem.map() {
+                self.read_map(map, &member(elem), elem.size_occurs().as_ref().unwrap(), "return false;")?;
+            } else if let Some(ref o) = elem.occurs() {
                 use ::flat_ast::Occurs::*;
                 match o {
                     Unbounded => {
-                        if let Some(ref s) = elem.size_occurs() {
-                            self.write_if_else(&format!("!reader.get_{}({}.size())", s, elem.name()), &[
+                        if let Some(ref count) = elem.occurs_ref().as_ref().or(elem.blob_length().as_ref()) {
+                            cg!(self, "for (size_t index = 0; index < {}; ++index) {{", count);
+                            self.indent();
+                            cg!(self, "{} elem;", item_type(elem, &type_));
+                            self.read_levels(elem, elem.inner_occurs(), &type_, "elem", &base, "", "return false;", 1)?;
+                            cg!(self, "{}.push_back(elem);", value);
+                            self.dedent();
+                            cg!(self, "}}");
+                        } else if elem.inner_occurs().is_empty() {
+                            if let Some(ref s) = elem.size_occurs() {
+                                self.write_if_else(&format!("!reader.get_{}({}.size())", s, value), &[
+                                    "return false;"
+                                ], None)?;
+                            }
+                            cg!(self, "for (const auto& elem : {}) {{", value);
+                            self.indent();
+                            self.write_if_else(&format!("!reader.get_{}(elem)", base), &[
+                                    "return false;"
+                                ], None)?;
+                            self.dedent();
+                            cg!(self, "}}");
+                        } else {
+                            // multi-dimensional elements always have their outer size written, see check_inner_occurs
+                            let s = elem.size_occurs().as_ref().unwrap();
+                            cg!(self, "{{");
+                            self.indent();
+                            cg!(self, "{} size;", s);
+                            self.write_if_else(&format!("!reader.get_{}(size)", s), &[
                                 "return false;"
                             ], None)?;
+                            cg!(self, "while (size-- > 0) {{");
+                            self.indent();
+                            cg!(self, "{} elem;", item_type(elem, &type_));
+                            self.read_levels(elem, elem.inner_occurs(), &type_, "elem", &base, "", "return false;", 1)?;
+                            cg!(self, "{}.push_back(elem);", value);
+                            self.dedent();
+                            cg!(self, "}}");
+                            self.dedent();
+                            cg!(self, "}}");
                         }
-                        cg!(self, "for (const auto& elem : {}) {{", elem.name());
-                        self.indent();
-                        self.write_if_else(&format!("!reader.get_{}(elem)", base), &[
-                                "return false;"
-                            ], None)?;
-                        self.dedent();
-                        cg!(self, "}}");
                     },
                     Num(n) => {
                         cg!(self, "for (size_t index = 0; index < {}; ++index) {{", n);
                         self.indent();
-                        self.write_if_else(&format!("!reader.get_{}({}[index])", base, elem.name()), &[
-                                "return false;"
-                            ], None)?;
+                        self.read_levels(elem, elem.inner_occurs(), &type_, &format!("{}[index]", value), &base, "", "return false;", 1)?;
                         self.dedent();
                         cg!(self, "}}");
                     }
@@ -905,20 +1163,32 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
                         None
                     }
                 } else {
-                    Some(elem.name())
+                    Some(&value)
                 };
                 let base = if elem.bitset().is_some() {
                     "bitset".to_owned()
                 } else {
                     base
                 };
-                if let Some(name) = name {
-                    self.write_if_else(&format!("!reader.get_{}({})", base, name), &[
+                if let Some(flags) = self.flags.get(&elem.type_().to_lower_camel_case()).cloned() {
+                    let temp_name = format!("{}_temp", elem.name());
+                    cg!(self, "{} {};", flags, temp_name);
+                    self.write_if_else(&format!("!reader.get_{}({})", flags, temp_name), &[
                             "return false;"
                         ], None)?;
+                    cg!(self, "{} = static_cast<{}>({});", value, type_, temp_name);
+                } else if let Some(name) = name {
+                    let fail = self.nested_fail(elem, name, "return false;");
+                    self.write_if_else(&format!("!reader.get_{}({})", base, name), &fail.iter().map(String::as_str).collect::<Vec<_>>(), None)?;
                 }
             }
+            self.check_fixed(elem, "return false;")?;
+            if elem.optional() {
+                self.close_presence()?;
+            }
+            self.close_condition(&guard)?;
         }
+        self.check_computes(&elements, class_name, iserialize, "return false;")?;
         cg!(self, "return true;");
         self.dedent();
         cg!(self, "}}");
@@ -1010,7 +1280,42 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         }).is_some();
         let base = restrict.base();
 
-        if !is_enum {
+        if let Some(ref scale) = restrict.scale() {
+            let value = scaled_type(base);
+            cg!(self, "{0}::{1}::{1}() : raw(0), is_valid(false) {{}}", class_name, name);
+            cg!(self);
+            cg!(self, "{0}::{1}::{1}({2} value) : is_valid(false) {{", class_name, name, value);
+            self.indent();
+            cg!(self, "const double raw_value = std::round((static_cast<double>(value) - {}) / {});", scale.offset, scale.factor);
+            cg!(self, "const double min = std::numeric_limits<{}>::min();", base);
+            cg!(self, "const double max = std::numeric_limits<{}>::max();", base);
+            cg!(self, "is_valid = raw_value >= min && raw_value <= max;");
+            cg!(self, "raw = static_cast<{}>(std::clamp(raw_value, min, max));", base);
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+            cg!(self, "bool {}::{}::read(CRoseReader& reader) {{", class_name, name);
+            self.indent();
+            self.write_if_else(&format!("!reader.get_{}(raw)", base), &["return false;"], None)?;
+            cg!(self, "is_valid = true;");
+            cg!(self, "return true;");
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+            cg!(self, "bool {}::{}::write(CRoseBasePolicy& writer) const {{", class_name, name);
+            self.indent();
+            self.write_if_else(&format!("!writer.set_{}(raw)", base), &["return false;"], None)?;
+            cg!(self, "return true;");
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+            cg!(self, "constexpr size_t {}::{}::size() {{", class_name, name);
+            self.indent();
+            cg!(self, "return sizeof({});", base);
+            self.dedent();
+            cg!(self, "}}");
+            cg!(self);
+        } else if !is_enum {
             let data = name.to_string().to_snake_case();
             cg!(self, "{0}::{1}::{1}() : is_valid(false) {{}}", class_name, name);
             cg!(self);
@@ -1139,6 +1444,471 @@ impl<'a, W: Write> CodeSourceGenerator<'a, W> {
         Ok(())
     }
 
+    fn read_map(&mut self, map: &MapType, target: &str, size: &str, fail: &str) -> Result<()> {
+        cg!(self, "{{");
+        self.indent();
+        cg!(self, "{} size;", size);
+        self.write_if_else(&format!("!reader.get_{}(size)", size), &[fail], None)?;
+        cg!(self, "while (size-- > 0) {{");
+        self.indent();
+        cg!(self, "{} key;", map.key);
+        cg!(self, "{} value;", map.value);
+        self.write_if_else(&format!("!reader.get_{}(key)", clean_base(&map.key)), &[fail], None)?;
+        self.write_if_else(&format!("!reader.get_{}(value)", clean_base(&map.value)), &[fail], None)?;
+        cg!(self, "{}[key] = value;", target);
+        self.dedent();
+        cg!(self, "}}");
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    fn write_map(&mut self, map: &MapType, source: &str, size: &str, fail: &str) -> Result<()> {
+        self.write_if_else(&format!("!writer.set_{}({}.size())", size, source), &[fail], None)?;
+        cg!(self, "for (const auto& elem : {}) {{", source);
+        self.indent();
+        self.write_if_else(&format!("!writer.set_{}(elem.first)", clean_base(&map.key)), &[fail], None)?;
+        self.write_if_else(&format!("!writer.set_{}(elem.second)", clean_base(&map.value)), &[fail], None)?;
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    // reads `target` one level at a time, looping over the nested levels of multi-dimensional elements
+    fn read_levels(&mut self, elem: &Element, levels: &[InnerOccurs], type_: &str, target: &str, base: &str, cast: &str, fail: &str, depth: usize) -> Result<()> {
+        let level = match levels.first() {
+            Some(level) => level,
+            None => {
+                let fail = self.nested_fail(elem, target, fail);
+                return self.write_if_else(&format!("!reader.get_{}({}{})", base, cast, target), &fail.iter().map(String::as_str).collect::<Vec<_>>(), None);
+            }
+        };
+        match level.occurs {
+            Occurs::Num(ref n) => {
+                cg!(self, "for (size_t index{0} = 0; index{0} < {1}; ++index{0}) {{", depth, n);
+                self.indent();
+                self.read_levels(elem, &levels[1..], type_, &format!("{}[index{}]", target, depth), base, cast, fail, depth + 1)?;
+            },
+            Occurs::Unbounded => {
+                let item = levels_type(&levels[1..], type_);
+                let size = level.size_occurs.as_ref().unwrap();
+                cg!(self, "{} size{};", size, depth);
+                self.write_if_else(&format!("!reader.get_{}(size{})", size, depth), &[fail], None)?;
+                cg!(self, "while (size{}-- > 0) {{", depth);
+                self.indent();
+                cg!(self, "{} elem{};", item, depth);
+                self.read_levels(elem, &levels[1..], type_, &format!("elem{}", depth), base, cast, fail, depth + 1)?;
+                cg!(self, "{}.push_back(elem{});", target, depth);
+            }
+        }
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    // a failed check inside a nested type is reported as the field holding it
+    fn nested_fail(&self, elem: &Element, target: &str, fail: &str) -> Vec<String> {
+        if !self.checked.contains(elem.type_()) {
+            return vec![fail.to_owned()];
+        }
+        vec![
+            format!("if ({}.get_invalid_field()) {{", target),
+            format!("    invalid_field = \"{}\";", elem.name()),
+            "}".to_owned(),
+            fail.to_owned()
+        ]
+    }
+
+    // writes `source` one level at a time, looping over the nested levels of multi-dimensional elements
+    fn write_levels(&mut self, levels: &[InnerOccurs], source: &str, base: &str, fail: &str, depth: usize) -> Result<()> {
+        let level = match levels.first() {
+            Some(level) => level,
+            None => return self.write_if_else(&format!("!writer.set_{}({})", base, source), &[fail], None)
+        };
+        if let Some(ref size) = level.size_occurs {
+            self.write_if_else(&format!("!writer.set_{}({}.size())", size, source), &[fail], None)?;
+        }
+        cg!(self, "for (const auto& elem{} : {}) {{", depth, source);
+        self.indent();
+        self.write_levels(&levels[1..], &format!("elem{}", depth), base, fail, depth + 1)?;
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    fn write_presence<'b>(&mut self, presence: &str, elements: impl Iterator<Item = &'b Element>, fail: &str) -> Result<()> {
+        cg!(self, "{} presence = 0;", presence);
+        for (bit, elem) in elements.filter(|elem| elem.optional()).enumerate() {
+            self.write_if_else(&format!("{}.has_value()", elem.name()), &[
+                &format!("presence |= static_cast<{}>(1) << {};", presence, bit)
+            ], None)?;
+        }
+        self.write_if_else(&format!("!writer.set_{}(presence)", presence), &[fail], None)
+    }
+
+    fn read_presence(&mut self, presence: &str, fail: &str) -> Result<()> {
+        cg!(self, "{} presence;", presence);
+        self.write_if_else(&format!("!reader.get_{}(presence)", presence), &[fail], None)
+    }
+
+    fn open_presence(&mut self, elem: &Element, presence: &Option<String>, bit: u32) -> Result<()> {
+        let presence = presence.as_ref().unwrap();
+        cg!(self, "if (presence & (static_cast<{}>(1) << {})) {{", presence, bit);
+        self.indent();
+        cg!(self, "{}.emplace();", elem.name());
+        Ok(())
+    }
+
+    fn close_presence(&mut self) -> Result<()> {
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    fn write_padding(&mut self, elem: &Element, fill: &str, fail: &str) -> Result<()> {
+        if let Some(Occurs::Num(ref n)) = elem.occurs() {
+            cg!(self, "for (size_t index = 0; index < {}; ++index) {{", n);
+            self.indent();
+            self.write_if_else(&format!("!writer.set_{}({})", elem.type_(), fill), &[fail], None)?;
+            self.dedent();
+            cg!(self, "}}");
+        } else {
+            self.write_if_else(&format!("!writer.set_{}({})", elem.type_(), fill), &[fail], None)?;
+        }
+        Ok(())
+    }
+
+    fn read_padding(&mut self, elem: &Element, fail: &str) -> Result<()> {
+        if let Some(Occurs::Num(ref n)) = elem.occurs() {
+            cg!(self, "for (size_t index = 0; index < {}; ++index) {{", n);
+        } else {
+            cg!(self, "{{");
+        }
+        self.indent();
+        cg!(self, "{} {};", elem.type_(), elem.name());
+        self.write_if_else(&format!("!reader.get_{}({})", elem.type_(), elem.name()), &[fail], None)?;
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    // serializes the range of a computed field into `bytes` exactly as the writer would send it
+    fn write_range(&mut self, elem: &Element, elements: &[&Element], class_name: &str, iserialize: &HashSet<String>, recompute: bool, fail: &str) -> Result<String> {
+        cg!(self, "std::vector<uint8_t> bytes;");
+        cg!(self, "auto range = [&](CRoseBasePolicy& writer) {{");
+        self.indent();
+        for name in elem.compute_range() {
+            if let Some(field) = elements.iter().find(|e| e.name() == name) {
+                self.write_element(field, elements, class_name, iserialize, recompute)?;
+            }
+        }
+        cg!(self, "return true;");
+        self.dedent();
+        cg!(self, "}};");
+        self.write_if_else("!written_bytes(bytes, range)", &[fail], None)?;
+        let compute = elem.compute().as_ref().unwrap();
+        Ok(match compute.kind {
+            ComputeKind::Length => format!("static_cast<{}>(bytes.size())", elem.type_()),
+            ComputeKind::Crc16 => format!("static_cast<{}>(crc16(bytes))", elem.type_()),
+            ComputeKind::Sum8 => format!("static_cast<{}>(sum8(bytes))", elem.type_())
+        })
+    }
+
+    fn write_compute(&mut self, elem: &Element, elements: &[&Element], class_name: &str, iserialize: &HashSet<String>, base: &str) -> Result<()> {
+        cg!(self, "{{");
+        self.indent();
+        let value = self.write_range(elem, elements, class_name, iserialize, true, "return false;")?;
+        self.write_if_else(&format!("!writer.set_{}({})", base, value), &[
+                "return false;"
+            ], None)?;
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    // computed fields are verified once everything they may cover has been read,
+    // the computed fields they cover are written as read
+    fn check_computes(&mut self, elements: &[&Element], class_name: &str, iserialize: &HashSet<String>, fail: &str) -> Result<()> {
+        for elem in elements.iter().filter(|e| e.compute().is_some()) {
+            cg!(self, "{{");
+            self.indent();
+            let value = self.write_range(elem, elements, class_name, iserialize, false, fail)?;
+            self.write_if_else(&format!("{} != {}", elem.name(), value), &[
+                &format!("invalid_field = \"{}\";", elem.name()),
+                fail
+            ], None)?;
+            self.dedent();
+            cg!(self, "}}");
+        }
+        Ok(())
+    }
+
+    fn map_helpers(&mut self, packet: &Packet) -> Result<()> {
+        let maps = packet.contents().iter().any(|content| match content {
+            PacketContent::Element(ref e) => e.map().is_some(),
+            PacketContent::Complex(ref c) => match c.content() {
+                ComplexTypeContent::Seq(ref s) => s.elements().iter().any(|e| e.map().is_some()),
+                _ => false
+            },
+            _ => false
+        });
+        if !maps {
+            return Ok(());
+        }
+        cg!(self, "namespace {{");
+        cg!(self, "// json object keys have to be strings, so integer map keys are converted");
+        cg!(self, "std::string map_key(const std::string& key) {{");
+        self.indent();
+        cg!(self, "return key;");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self);
+        cg!(self, "template <typename T>");
+        cg!(self, "std::string map_key(const T& key) {{");
+        self.indent();
+        cg!(self, "return std::to_string(key);");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self);
+        cg!(self, "template <typename Map>");
+        cg!(self, "nlohmann::json map_to_json(const Map& values) {{");
+        self.indent();
+        cg!(self, "nlohmann::json j = nlohmann::json::object();");
+        cg!(self, "for (const auto& elem : values) {{");
+        self.indent();
+        cg!(self, "j[map_key(elem.first)] = elem.second;");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self, "return j;");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self, "}}");
+        cg!(self);
+        Ok(())
+    }
+
+    fn compute_helpers(&mut self, packet: &Packet) -> Result<()> {
+        let computes = packet.contents().iter().any(|content| match content {
+            PacketContent::Element(ref e) => e.compute().is_some(),
+            PacketContent::Complex(ref c) => match c.content() {
+                ComplexTypeContent::Seq(ref s) => s.elements().iter().any(|e| e.compute().is_some()),
+                _ => false
+            },
+            _ => false
+        });
+        if !computes {
+            return Ok(());
+        }
+        cg!(self, "namespace {{");
+        cg!(self, "// serializes the fields covered by a computed length or checksum with the runtime writer,");
+        cg!(self, "// so the value is computed over the bytes actually sent");
+        cg!(self, "template <typename Range>");
+        cg!(self, "bool written_bytes(std::vector<uint8_t>& bytes, Range range) {{");
+        self.indent();
+        cg!(self, "CRoseSizer sizer;");
+        self.write_if_else("!range(sizer)", &["return false;"], None)?;
+        cg!(self, "bytes.resize(sizer.get_size());");
+        cg!(self, "CRoseWriter writer(bytes.data(), bytes.size());");
+        cg!(self, "return range(writer);");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self);
+        cg!(self, "// CRC-16/ARC");
+        cg!(self, "uint16_t crc16(const std::vector<uint8_t>& bytes) {{");
+        self.indent();
+        cg!(self, "uint16_t crc = 0;");
+        cg!(self, "for (uint8_t byte : bytes) {{");
+        self.indent();
+        cg!(self, "crc ^= byte;");
+        cg!(self, "for (int bit = 0; bit < 8; ++bit) {{");
+        self.indent();
+        cg!(self, "crc = (crc & 1) ? (crc >> 1) ^ 0xA001 : crc >> 1;");
+        self.dedent();
+        cg!(self, "}}");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self, "return crc;");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self);
+        cg!(self, "uint8_t sum8(const std::vector<uint8_t>& bytes) {{");
+        self.indent();
+        cg!(self, "uint8_t sum = 0;");
+        cg!(self, "for (uint8_t byte : bytes) {{");
+        self.indent();
+        cg!(self, "sum += byte;");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self, "return sum;");
+        self.dedent();
+        cg!(self, "}}");
+        cg!(self, "}}");
+        cg!(self);
+        Ok(())
+    }
+
+    fn check_fixed(&mut self, elem: &Element, fail: &str) -> Result<()> {
+        if let Some(ref fixed) = elem.fixed() {
+            self.write_if_else(&format!("{} != {}", elem.name(), fixed), &[
+                &format!("invalid_field = \"{}\";", elem.name()),
+                fail
+            ], None)?;
+        }
+        Ok(())
+    }
+
+    fn size_signature(&mut self, class_name: &str, dynamic: bool) -> Result<()> {
+        if dynamic {
+            cg!(self, "size_t {}::size() const {{", class_name);
+        } else {
+            cg!(self, "constexpr size_t {}::size() {{", class_name);
+        }
+        Ok(())
+    }
+
+    fn packet_elem_size(&mut self, elem: &Element, iserialize: &HashSet<String>) -> Result<()> {
+        if let Some(ref size) = elem.size_occurs() {
+            cg!(self, "size += sizeof({}); // {}", size, elem.name());
+        }
+        if let Some(ref map) = elem.map() {
+            return self.map_size(elem, map);
+        }
+        if self.dynamic.contains(elem.type_()) {
+            return self.dynamic_elem_size(elem);
+        }
+        let rhs = if iserialize.contains(&elem.type_().to_owned().to_lower_camel_case()) && elem.enum_type().is_none() {
+            format!("{}::size()", elem.type_())
+        } else {
+            match elem.bitset() {
+                Some(ref bitset) if bitset.start == 0 => format!("{} / 8", bitset.size),
+                Some(_) => return Ok(()),
+                None => format!("sizeof({})", elem.type_())
+            }
+        };
+        self.elem_size(elem, rhs)
+    }
+
+    fn sequence_elem_size(&mut self, elem: &Element, iserialize: &HashSet<String>) -> Result<()> {
+        if let Some(ref map) = elem.map() {
+            cg!(self, "size += sizeof({}); // {}", elem.size_occurs().as_ref().unwrap(), elem.name());
+            return self.map_size(elem, map);
+        }
+        if self.dynamic.contains(elem.type_()) {
+            return self.dynamic_elem_size(elem);
+        }
+        let rhs = if iserialize.contains(&elem.type_().to_owned().to_lower_camel_case())
+            && !self.flags.contains_key(&elem.type_().to_lower_camel_case()) {
+            format!("{}::size()", elem.type_())
+        } else {
+            match elem.bitset() {
+                Some(ref bitset) if bitset.start == 0 => format!("{} / 8", bitset.size),
+                Some(_) => return Ok(()),
+                None => format!("sizeof({})", elem.type_())
+            }
+        };
+        self.elem_size(elem, rhs)
+    }
+
+    fn elem_size(&mut self, elem: &Element, rhs: String) -> Result<()> {
+        let rhs = levels_size(elem.inner_occurs(), rhs);
+        let rhs = if let Some(ref o) = elem.occurs() {
+            use ::flat_ast::Occurs::*;
+            match o {
+                Unbounded => match elem.blob_length() {
+                    Some(ref length) => rhs + " * " + length,
+                    None if elem.blob() => format!("{} * {}.size()", rhs, member(elem)),
+                    None => rhs
+                },
+                Num(n) => rhs + " * " + n
+            }
+        } else {
+            rhs
+        };
+        let name = if let Some(bitset) = elem.bitset() {
+            &bitset.name
+        } else {
+            elem.name()
+        };
+        cg!(self, "size += {}; // {}", rhs, name);
+        Ok(())
+    }
+
+    // the size of a type with conditional fields is only known from its value, every entry is asked for it
+    fn dynamic_elem_size(&mut self, elem: &Element) -> Result<()> {
+        let mut value = member(elem);
+        let mut depth = 0;
+        if elem.occurs().is_some() {
+            cg!(self, "for (const auto& item{} : {}) {{", depth, value);
+            self.indent();
+            value = format!("item{}", depth);
+            depth += 1;
+        }
+        for level in elem.inner_occurs() {
+            if let Some(ref size) = level.size_occurs {
+                cg!(self, "size += sizeof({});", size);
+            }
+            cg!(self, "for (const auto& item{} : {}) {{", depth, value);
+            self.indent();
+            value = format!("item{}", depth);
+            depth += 1;
+        }
+        cg!(self, "size += {}.size(); // {}", value, elem.name());
+        for _ in 0..depth {
+            self.dedent();
+            cg!(self, "}}");
+        }
+        Ok(())
+    }
+
+    // every entry is written as its key then its value, strings with their terminator
+    fn map_size(&mut self, elem: &Element, map: &MapType) -> Result<()> {
+        let entry_size = |value: &str, type_: &str| if type_ == "std::string" {
+            format!("{}.size() + 1", value)
+        } else {
+            format!("sizeof({})", type_)
+        };
+        cg!(self, "for (const auto& elem : {}) {{", member(elem));
+        self.indent();
+        cg!(self, "size += {}; // {} key", entry_size("elem.first", &map.key), elem.name());
+        cg!(self, "size += {}; // {} value", entry_size("elem.second", &map.value), elem.name());
+        self.dedent();
+        cg!(self, "}}");
+        Ok(())
+    }
+
+    // an absent optional field isn't written, its presence bit is set from the member
+    fn open_optional(&mut self, elem: &Element) -> Result<()> {
+        if elem.optional() {
+            cg!(self, "if ({}) {{", elem.name());
+            self.indent();
+        }
+        Ok(())
+    }
+
+    fn close_optional(&mut self, elem: &Element) -> Result<()> {
+        if elem.optional() {
+            self.dedent();
+            cg!(self, "}}");
+        }
+        Ok(())
+    }
+
+    fn open_condition(&mut self, guard: &Option<String>) -> Result<()> {
+        if let Some(ref guard) = guard {
+            cg!(self, "if ({}) {{", guard);
+            self.indent();
+        }
+        Ok(())
+    }
+
+    fn close_condition(&mut self, guard: &Option<String>) -> Result<()> {
+        if guard.is_some() {
+            self.dedent();
+            cg!(self, "}}");
+        }
+        Ok(())
+    }
+
     fn write_if_else(&mut self, condition: &str, if_branch: &[&str], else_branch: Option<&[&str]>) -> Result<()> {
         cg!(self, "if ({}) {{", condition);
         self.indent();
@@ -1166,3 +1936,51 @@ fn clean_base(base: &str) -> String {
         base.to_string()
     }
 }
+
+// the size of one entry of the outer level of a multi-dimensional element, counting the size of every nested level
+fn levels_size(levels: &[InnerOccurs], item: String) -> String {
+    levels.iter().rev().fold(item, |item, level| {
+        let items = match level.occurs {
+            Occurs::Num(ref n) => format!("{} * {}", item, n),
+            Occurs::Unbounded => item
+        };
+        match level.size_occurs {
+            Some(ref size) => format!("(sizeof({}) + {})", size, items),
+            None => items
+        }
+    })
+}
+
+fn packet_elements(packet: &Packet) -> Vec<&Element> {
+    packet.contents().iter().filter_map(|content| match content {
+        PacketContent::Element(elem) => Some(&**elem),
+        _ => None
+    }).collect()
+}
+
+// the guard of a conditional element, enum values are qualified so they also resolve outside of the class
+fn condition_expr(elem: &Element, elements: &[&Element], class_name: &str, owner: &str) -> Option<String> {
+    let condition = elem.condition().as_ref()?;
+    let field = elements.iter().find(|e| *e.name() == condition.field)?;
+    let is_ident = condition.value.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !condition.value.starts_with(|c: char| c.is_ascii_digit());
+    let value = if field.is_defined() && is_ident {
+        format!("{}::{}::{}", class_name, field.type_(), condition.value)
+    } else {
+        condition.value.clone()
+    };
+    let getter = format!("{}get_{}()", owner, field.name());
+    Some(match condition.op {
+        ConditionOp::Eq => format!("{} == {}", getter, value),
+        ConditionOp::Ne => format!("{} != {}", getter, value),
+        ConditionOp::Mask => format!("static_cast<bool>({} & {})", getter, value)
+    })
+}
+
+fn member(elem: &Element) -> String {
+    if elem.optional() {
+        format!("{}.value()", elem.name())
+    } else {
+        elem.name().to_owned()
+    }
+}
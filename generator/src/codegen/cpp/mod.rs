@@ -1,6 +1,6 @@
-use std::fs::File;
-use std::path::PathBuf;
-use codegen::Codegen;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use codegen::{Codegen, GeneratedFile};
 use ::{flat_ast, writer};
 
 mod codegen_header;
@@ -8,46 +8,213 @@ mod codegen_source;
 
 pub struct Generator {
     output_header: PathBuf,
-    output_source: PathBuf
+    output_source: PathBuf,
+    namespace: String
 }
 
 impl Generator {
+    // the arguments are complete once merged with the project file
     pub fn new(args: &CppArgs) -> Self {
         Self{
-            output_header: args.output_header_folder.clone().into(),
-            output_source: args.output_source_folder.clone().into()
+            output_header: args.output_header_folder.clone().unwrap_or_default().into(),
+            output_source: args.output_source_folder.clone().unwrap_or_default().into(),
+            namespace: args.namespace.clone().unwrap_or_else(|| DEFAULT_NAMESPACE.to_owned())
         }
     }
 }
 
 impl Codegen for Generator {
-    fn generate(&mut self, version: &str, packet: &flat_ast::Packet) -> Result<(), failure::Error> {
-        let header_output = File::create(self.output_header.to_str().unwrap().to_owned() + &format!("/{}.h", packet.filename()))?;
+    fn output_dirs(&self) -> Vec<PathBuf> {
+        vec![self.output_header.clone(), self.output_source.clone()]
+    }
+
+    fn generate(&mut self, version: &str, input_hash: u64, packet: &flat_ast::Packet) -> Result<Vec<GeneratedFile>, failure::Error> {
+        let header_output = self.output_header.join(format!("{}.h", packet.filename()));
         debug!("header {:?}", header_output);
-        let mut writer = writer::Writer::new(header_output);
+        let mut writer = writer::Writer::new(Vec::new());
         let mut codegen = codegen_header::CodeHeaderGenerator::new(&mut writer, version.to_string());
+        codegen.set_namespace(self.namespace.clone());
+        codegen.set_input_hash(input_hash);
         codegen.generate(&packet)?;
-        let source_output = File::create(self.output_source.to_str().unwrap().to_owned() + &format!("/{}.cpp", packet.filename()))?;
+        let header = GeneratedFile { path: header_output, contents: writer.into() };
+        let source_output = self.output_source.join(format!("{}.cpp", packet.filename()));
         debug!("source {:?}", source_output);
-        let mut writer = writer::Writer::new(source_output);
-        let mut codegen = codegen_source::CodeSourceGenerator::new(&mut writer);
+        let mut writer = writer::Writer::new(Vec::new());
+        let mut codegen = codegen_source::CodeSourceGenerator::new(&mut writer, version.to_string());
+        codegen.set_namespace(self.namespace.clone());
+        codegen.set_input_hash(input_hash);
         codegen.generate(&packet)?;
-        Ok(())
+        let source = GeneratedFile { path: source_output, contents: writer.into() };
+        Ok(vec![header, source])
+    }
+
+    fn generate_types(&mut self, version: &str, input_hash: u64, types: &flat_ast::Packet) -> Result<Vec<GeneratedFile>, failure::Error> {
+        let header_output = self.output_header.join(format!("{}.h", types.filename()));
+        debug!("header {:?}", header_output);
+        let mut writer = writer::Writer::new(Vec::new());
+        let mut codegen = codegen_header::CodeHeaderGenerator::new(&mut writer, version.to_string());
+        codegen.set_namespace(self.namespace.clone());
+        codegen.set_input_hash(input_hash);
+        codegen.generate_types(types)?;
+        let header = GeneratedFile { path: header_output, contents: writer.into() };
+        let source_output = self.output_source.join(format!("{}.cpp", types.filename()));
+        debug!("source {:?}", source_output);
+        let mut writer = writer::Writer::new(Vec::new());
+        let mut codegen = codegen_source::CodeSourceGenerator::new(&mut writer, version.to_string());
+        codegen.set_namespace(self.namespace.clone());
+        codegen.set_input_hash(input_hash);
+        codegen.generate_types(types)?;
+        let source = GeneratedFile { path: source_output, contents: writer.into() };
+        Ok(vec![header, source])
+    }
+}
+
+pub(crate) fn item_type(elem: &flat_ast::Element, type_: &str) -> String {
+    levels_type(elem.inner_occurs(), type_)
+}
+
+pub(crate) fn levels_type(levels: &[flat_ast::InnerOccurs], type_: &str) -> String {
+    levels.iter().rev().fold(type_.to_owned(), |type_, inner| match inner.occurs {
+        flat_ast::Occurs::Unbounded => format!("std::vector<{}>", type_),
+        flat_ast::Occurs::Num(ref n) => format!("std::array<{}, {}>", type_, n)
+    })
+}
+
+// complex types whose size depends on a field condition, either their own or one of an element's type
+pub(crate) fn dynamic_sizes(packet: &flat_ast::Packet) -> HashSet<String> {
+    let mut dynamic = HashSet::new();
+    loop {
+        let count = dynamic.len();
+        for content in packet.contents() {
+            if let flat_ast::PacketContent::Complex(ref complex) = content {
+                if let flat_ast::ComplexTypeContent::Seq(ref s) = complex.content() {
+                    if has_dynamic_size(s.elements().iter(), &dynamic) {
+                        dynamic.insert(complex.name().to_owned());
+                    }
+                }
+            }
+        }
+        if dynamic.len() == count {
+            return dynamic;
+        }
+    }
+}
+
+// a dynamic size can't be known at compile time, `size()` is then computed from the instance
+pub(crate) fn has_dynamic_size<'a>(mut elements: impl Iterator<Item = &'a flat_ast::Element>, dynamic: &HashSet<String>) -> bool {
+    elements.any(|elem| elem.condition().is_some() || elem.optional() || dynamic.contains(elem.type_())
+        || (elem.blob() && elem.blob_length().is_none()) || elem.map().is_some())
+}
+
+// the types generated in a shared or imported types file, the others come from another one and are only aliased
+pub(crate) fn is_defined_in(content: &flat_ast::PacketContent, types: &flat_ast::Packet) -> bool {
+    flat_ast::PacketContent::origin(content).is_none_or(|origin| origin.namespace == *types.class_name())
+}
+
+// elements checked after reading, a mismatch is reported through `get_invalid_field()`
+pub(crate) fn is_validated(elem: &flat_ast::Element) -> bool {
+    elem.fixed().is_some() || elem.compute().is_some()
+}
+
+// the types reporting a failed check through `get_invalid_field()`, also when it comes from one of their fields
+pub(crate) fn checked_types(packet: &flat_ast::Packet) -> HashSet<String> {
+    let mut checked = HashSet::new();
+    loop {
+        let count = checked.len();
+        for content in packet.contents() {
+            if let flat_ast::PacketContent::Complex(ref complex) = content {
+                if let flat_ast::ComplexTypeContent::Seq(ref s) = complex.content() {
+                    if has_checks(s.elements().iter(), &checked) {
+                        checked.insert(complex.name().to_owned());
+                    }
+                }
+            }
+        }
+        if checked.len() == count {
+            return checked;
+        }
     }
 }
 
+pub(crate) fn has_checks<'a>(mut elements: impl Iterator<Item = &'a flat_ast::Element>, checked: &HashSet<String>) -> bool {
+    elements.any(|elem| is_validated(elem) || checked.contains(elem.type_()))
+}
+
+// the type a scaled integer is exposed as, float is exact for 16-bit raw values
+pub(crate) fn scaled_type(base: &str) -> &'static str {
+    match base {
+        "int8_t" | "uint8_t" | "int16_t" | "uint16_t" => "float",
+        _ => "double"
+    }
+}
+
+pub const DEFAULT_NAMESPACE: &str = "RoseCommon::Packet";
+// where CRosePacket, CRoseReader and the rest of the runtime live
+pub const RUNTIME_NAMESPACE: &str = "RoseCommon";
+
+// packets generated outside of the runtime's namespace have to bring it in to find its classes
+pub(crate) fn in_runtime_namespace(namespace: &str) -> bool {
+    namespace == RUNTIME_NAMESPACE || namespace.starts_with(&format!("{}::", RUNTIME_NAMESPACE))
+}
+
 #[derive(clap::Args, Debug)]
 #[command(name="cpp")]
 pub struct CppArgs {
     #[arg(long)]
-    output_header_folder: String,
+    output_header_folder: Option<String>,
+    #[arg(long)]
+    output_source_folder: Option<String>,
+    /// Namespace the packets are generated in
     #[arg(long)]
-    output_source_folder: String
+    namespace: Option<String>
+}
+
+impl CppArgs {
+    // fills the arguments missing from the command line from the `[cpp]` table of the project file
+    pub fn merge(&mut self, config: Option<&CppConfig>) -> Result<(), failure::Error> {
+        if let Some(config) = config {
+            self.output_header_folder = self.output_header_folder.take().or_else(|| config.output_header_folder.clone());
+            self.output_source_folder = self.output_source_folder.take().or_else(|| config.output_source_folder.clone());
+            self.namespace = self.namespace.take().or_else(|| config.namespace.clone());
+        }
+        if self.output_header_folder.is_none() || self.output_source_folder.is_none() {
+            return Err(failure::err_msg("the cpp backend needs an output header and source folder"));
+        }
+        Ok(())
+    }
+
+    pub fn from_config(config: &CppConfig) -> Result<Self, failure::Error> {
+        let mut args = CppArgs { output_header_folder: None, output_source_folder: None, namespace: None };
+        args.merge(Some(config))?;
+        Ok(args)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CppConfig {
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub output_header_folder: Option<String>,
+    pub output_source_folder: Option<String>,
+    pub namespace: Option<String>
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl CppConfig {
+    pub(crate) fn relative_to(&mut self, dir: &Path) {
+        for folder in vec![&mut self.output_header_folder, &mut self.output_source_folder].into_iter().flatten() {
+            *folder = ::config::relative_to(dir, Path::new(folder)).display().to_string();
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{flat_ast::Packet, writer::Writer};
+    use crate::{flat_ast::{Packet, PacketContent, Element, ElementInitValue}, writer::Writer};
     use super::{codegen_header};
 
     struct StringWriter {
@@ -91,4 +258,134 @@ mod tests {
         let result = call_header(&packet);
         assert!(result.is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn optional_element() {
+        let mut packet = Packet::new("PAKCS_PACKET".to_owned(), None);
+        packet.set_presence("uint8_t".to_owned());
+        let mut elem = Element::new("hp".to_owned(), "uint16_t".to_owned(), 0, ElementInitValue::Create,
+            None, None, None, false, false, None, None, None);
+        elem.set_optional(true);
+        packet.add_content(PacketContent::Element(Box::new(elem)));
+        let result = call_header(&packet).unwrap();
+        assert!(result.contains("std::optional<uint16_t> hp;"));
+        assert!(result.contains("reset_hp();"));
+        assert!(result.contains("create();"));
+    }
+
+    #[test]
+    fn absent_optional_size() {
+        use crate::codegen::Codegen;
+        let args = super::CppArgs { output_header_folder: None, output_source_folder: None, namespace: None };
+        let mut packet = Packet::new("PAKSC_CHAR_HP".to_owned(), None);
+        packet.set_presence("uint8_t".to_owned());
+        let mut elem = Element::new("hp".to_owned(), "uint16_t".to_owned(), 0, ElementInitValue::Create,
+            None, None, None, false, false, None, None, None);
+        elem.set_optional(true);
+        packet.add_content(PacketContent::Element(Box::new(elem)));
+        let files = super::Generator::new(&args).generate("1.0", 0, &packet).unwrap();
+        let source = String::from_utf8(files[1].contents.clone()).unwrap();
+        assert!(source.contains("size_t SrvCharHp::size() const {"));
+        assert!(source.contains("if (hp) {\n        size += sizeof(uint16_t); // hp\n    }"));
+    }
+
+    // the generated source of a schema, run through the same passes as the binary
+    fn call_source(xml: &str) -> String {
+        call_generator(xml, None).1
+    }
+
+    // returns the generated header and source
+    fn call_generator(xml: &str, namespace: Option<&str>) -> (String, String) {
+        use crate::codegen::Codegen;
+        use crate::flatten::{self, IncludeCache, Options, PacketNames};
+        let packet = ::schema::Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).unwrap();
+        let (cache, packets) = (IncludeCache::default(), PacketNames::new());
+        let options = Options { version: None, profiles: &[], shared: false, include_dirs: &[], cache: &cache, packets: &packets };
+        let (packet, _) = flatten::flatten(std::path::Path::new("test.xml"), &packet, &options).unwrap();
+        let packet = crate::graph_passes::run(packet).unwrap();
+        let args = super::CppArgs { output_header_folder: None, output_source_folder: None, namespace: namespace.map(str::to_owned) };
+        let files = super::Generator::new(&args).generate("1.0", 0, &packet).unwrap();
+        (String::from_utf8(files[0].contents.clone()).unwrap(), String::from_utf8(files[1].contents.clone()).unwrap())
+    }
+
+    #[test]
+    fn custom_namespace() {
+        let xml = r#"<packet ePacketType="PAKSC_CHAR_HP">
+            <element name="hp" type="uint32_t"/>
+        </packet>"#;
+        let (header, source) = call_generator(xml, Some("Game::Net"));
+        assert!(header.contains("namespace Game {\nnamespace Net {\n\nusing namespace RoseCommon;\n"));
+        assert!(source.contains("using namespace RoseCommon;\nusing namespace Game;\nusing namespace Game::Net;\n"));
+
+        for namespace in &[None, Some("RoseCommon::Custom")] {
+            let (header, source) = call_generator(xml, *namespace);
+            assert!(!header.contains("using namespace RoseCommon;"));
+            assert_eq!(source.matches("using namespace RoseCommon;").count(), 1);
+        }
+    }
+
+    #[test]
+    fn nested_invalid_field() {
+        let source = call_source(r#"<packet ePacketType="PAKSC_CHAR_STATS">
+            <complexType name="magicHeader">
+                <sequence>
+                    <element name="magic" type="uint16_t" fixed="0x1234"/>
+                </sequence>
+            </complexType>
+            <complexType name="wrapped">
+                <sequence>
+                    <element name="header" type="magicHeader"/>
+                </sequence>
+            </complexType>
+            <element name="wrapper" type="wrapped"/>
+            <element name="headers" type="magicHeader" occurs="2"/>
+        </packet>"#);
+        assert!(source.contains("const char* SrvCharStats::wrapped::get_invalid_field() const {"));
+        assert!(source.contains("const char* SrvCharStats::get_invalid_field() const {"));
+        assert!(source.contains("if (header.get_invalid_field()) {\n            invalid_field = \"header\";\n        }"));
+        assert!(source.contains("if (wrapper.get_invalid_field()) {\n            invalid_field = \"wrapper\";\n        }"));
+        assert!(source.contains("if (headers[index].get_invalid_field()) {\n                invalid_field = \"headers\";\n            }"));
+    }
+
+    #[test]
+    fn blob_size() {
+        let source = call_source(r#"<packet ePacketType="PAKSC_RELAY">
+            <bytes name="key" length="16"/>
+            <bytes name="payload" occursSize="uint16_t"/>
+        </packet>"#);
+        assert!(source.contains("size_t SrvRelay::size() const {"));
+        assert!(source.contains("size += sizeof(uint8_t) * 16; // key"));
+        assert!(source.contains("size += sizeof(uint16_t); // payload\n    size += sizeof(uint8_t) * payload.size(); // payload"));
+    }
+
+    #[test]
+    fn map_size() {
+        let source = call_source(r#"<packet ePacketType="PAKSC_STATS">
+            <map name="stats" keyType="uint16_t" valueType="int32_t" occursSize="uint8_t"/>
+            <map name="names" keyType="uint8_t" valueType="std::string" occursSize="uint16_t" ordered="true"/>
+        </packet>"#);
+        assert!(source.contains("size_t SrvStats::size() const {"));
+        assert!(source.contains("size += sizeof(uint8_t); // stats
+    for (const auto& elem : stats) {
+        size += sizeof(uint16_t); // stats key
+        size += sizeof(int32_t); // stats value
+    }"));
+        assert!(source.contains("for (const auto& elem : names) {
+        size += sizeof(uint8_t); // names key
+        size += elem.second.size() + 1; // names value
+    }"));
+    }
+
+    #[test]
+    fn input_hash_banner() {
+        use crate::codegen::Codegen;
+        let args = super::CppArgs { output_header_folder: Some("include".to_owned()), output_source_folder: Some("src".to_owned()), namespace: None };
+        let packet = Packet::new("PAKCS_PACKET".to_owned(), None);
+        let files = super::Generator::new(&args).generate("1.0", 0x1234, &packet).unwrap();
+        assert_eq!(files.iter().map(|file| file.path.display().to_string()).collect::<Vec<_>>(), vec!["include/cli_packet.h", "src/cli_packet.cpp"]);
+        for file in files {
+            let contents = String::from_utf8(file.contents).unwrap();
+            assert!(contents.contains("/* Generated with IDL v1.0 (input hash 0000000000001234) */"));
+        }
+    }
+}
@@ -1,4 +1,7 @@
 use flat_ast;
+use std::fs;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
 
 #[macro_use]
 mod macros {
@@ -11,8 +14,69 @@ mod macros {
 }
 
 // the codegen trait, implement this for your language
+// both functions give back the files they generated, written or verified by the caller,
+// `input_hash` is the hash of the xml files they come from
 pub(crate) trait Codegen {
-    fn generate(&mut self, version: &str, packet: &flat_ast::Packet) -> Result<(), failure::Error>;
+    // the directories the files are generated in
+    fn output_dirs(&self) -> Vec<PathBuf>;
+    fn generate(&mut self, version: &str, input_hash: u64, packet: &flat_ast::Packet) -> Result<Vec<GeneratedFile>, failure::Error>;
+    // the types shared by the packets, gathered in a packet without elements
+    fn generate_types(&mut self, version: &str, input_hash: u64, types: &flat_ast::Packet) -> Result<Vec<GeneratedFile>, failure::Error>;
+}
+
+pub(crate) struct GeneratedFile {
+    pub path: PathBuf,
+    pub contents: Vec<u8>
+}
+
+// starts every generated file, only the files holding it near their top are ever removed
+pub(crate) const BANNER: &str = "Generated with IDL v";
+
+pub(crate) fn banner(version: &str, input_hash: Option<u64>) -> String {
+    match input_hash {
+        Some(hash) => format!("{}{} (input hash {:016x})", BANNER, version, hash),
+        None => format!("{}{}", BANNER, version)
+    }
+}
+
+// the hash of the contents of the files, it ends up in the generated files so it is the same on every machine and toolchain
+pub(crate) fn input_hash(files: &[PathBuf]) -> Result<u64, failure::Error> {
+    let mut hasher = ::fnv::FnvHasher::default();
+    for file in files {
+        let contents = fs::read(file)
+            .map_err(|e| failure::err_msg(format!("cannot read {}: {}", file.display(), e)))?;
+        hasher.write(&contents);
+        hasher.write_u8(0xff);
+    }
+    Ok(hasher.finish())
+}
+
+// a unified diff from the file on disk to the generated one, `None` when they are the same
+pub(crate) fn verify(file: &GeneratedFile) -> Option<String> {
+    let existing = fs::read(&file.path).unwrap_or_default();
+    if existing == file.contents {
+        return None;
+    }
+    let existing = String::from_utf8_lossy(&existing);
+    let generated = String::from_utf8_lossy(&file.contents);
+    let path = file.path.display().to_string();
+    let diff = ::similar::TextDiff::from_lines(existing.as_ref(), generated.as_ref());
+    let old = if file.path.is_file() { path.as_str() } else { "/dev/null" };
+    Some(diff.unified_diff().header(old, &path).to_string())
+}
+
+pub(crate) fn is_generated(path: &Path) -> bool {
+    fs::read_to_string(path).is_ok_and(|contents| contents.lines().take(5).any(|line| line.contains(BANNER)))
+}
+
+// files with the same contents are left untouched, so their modification time doesn't trigger rebuilds
+pub(crate) fn write_if_changed(path: &Path, contents: &[u8]) -> Result<(), failure::Error> {
+    if fs::read(path).is_ok_and(|existing| existing == contents) {
+        debug!("{} is unchanged", path.display());
+        return Ok(());
+    }
+    fs::write(path, contents)?;
+    Ok(())
 }
 
 pub mod cpp;
@@ -23,4 +87,68 @@ use clap::Subcommand;
 pub enum CodegenCommands {
     #[command(name = "cpp")]
     CppCommand(cpp::CppArgs)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GeneratedFile;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn dir(test: &str) -> PathBuf {
+        ::testing::temp_dir(&format!("codegen_{}", test))
+    }
+
+    #[test]
+    fn verify() {
+        let dir = dir("verify");
+        let path = dir.join("a.h");
+        let file = GeneratedFile { path: path.clone(), contents: b"one\ntwo\nthree\n".to_vec() };
+        let diff = super::verify(&file).unwrap();
+        assert!(diff.starts_with(&format!("--- /dev/null\n+++ {}\n", path.display())));
+        assert!(diff.contains("+one\n+two\n+three\n"));
+        fs::write(&path, "one\n2\nthree\n").unwrap();
+        let diff = super::verify(&file).unwrap();
+        assert!(diff.starts_with(&format!("--- {0}\n+++ {0}\n", path.display())));
+        assert!(diff.contains("-2\n+two\n"));
+        fs::write(&path, &file.contents).unwrap();
+        assert_eq!(super::verify(&file), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn input_hash() {
+        let dir = dir("input_hash");
+        let (a, b) = (dir.join("a.xml"), dir.join("b.xml"));
+        fs::write(&a, "<packet/>").unwrap();
+        fs::write(&b, "").unwrap();
+        // FNV-1a over the contents, each file followed by 0xff, the same on every machine and toolchain
+        let hash = super::input_hash(&[a.clone(), b.clone()]).unwrap();
+        assert_eq!(hash, 0xbf3c_ead9_edf6_0fa8);
+        assert_ne!(super::input_hash(&[b.clone(), a.clone()]).unwrap(), hash);
+        fs::write(&b, " ").unwrap();
+        assert_ne!(super::input_hash(&[a.clone(), b.clone()]).unwrap(), hash);
+        assert!(super::input_hash(&[dir.join("missing.xml")]).is_err());
+
+        assert_eq!(super::banner("1.0", Some(0xab)), "Generated with IDL v1.0 (input hash 00000000000000ab)");
+        assert_eq!(super::banner("1.0", None), "Generated with IDL v1.0");
+        fs::write(&a, format!("#pragma once\n\n/* {} */\n", super::banner("1.0", Some(hash)))).unwrap();
+        assert!(super::is_generated(&a));
+        assert!(!super::is_generated(&b));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_if_changed() {
+        let dir = dir("write_if_changed");
+        let path = dir.join("a.h");
+        super::write_if_changed(&path, b"a").unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        ::std::thread::sleep(::std::time::Duration::from_millis(20));
+        super::write_if_changed(&path, b"a").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+        super::write_if_changed(&path, b"b").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"b");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
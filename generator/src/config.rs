@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use codegen::cpp;
+
+// the project file, `idl.toml`, each key is overridden by the command line flag of the same name
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // input files, directories and globs
+    pub inputs: Vec<String>,
+    pub include_dirs: Vec<PathBuf>,
+    pub protocol_version: Option<u32>,
+    pub profiles: Vec<String>,
+    pub shared_types: bool,
+    pub jobs: Option<usize>,
+    pub cache: Option<PathBuf>,
+    pub depfile: Option<PathBuf>,
+    pub cmake: Option<PathBuf>,
+    pub packets: Vec<String>,
+    pub exclude: Vec<String>,
+    pub clean_stale: bool,
+    // the backends, the ones present and not disabled run unless one is picked on the command line
+    pub cpp: Option<cpp::CppConfig>
+}
+
+pub const DEFAULT_CONFIG: &str = "idl.toml";
+
+impl Config {
+    // paths in the file are relative to its directory
+    pub fn load(path: &Path) -> Result<Self, ::failure::Error> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| ::failure::err_msg(format!("cannot read {}: {}", path.display(), e)))?;
+        let mut config: Config = ::toml::from_str(&contents)
+            .map_err(|e| ::failure::err_msg(format!("{}: {}", path.display(), e)))?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => PathBuf::from(".")
+        };
+        config.inputs = config.inputs.iter().map(|input| relative_to(&dir, Path::new(input)).display().to_string()).collect();
+        config.include_dirs = config.include_dirs.iter().map(|include| relative_to(&dir, include)).collect();
+        for path in vec![&mut config.cache, &mut config.depfile, &mut config.cmake].into_iter().flatten() {
+            *path = relative_to(&dir, path);
+        }
+        if let Some(ref mut cpp) = config.cpp {
+            cpp.relative_to(&dir);
+        }
+        Ok(config)
+    }
+}
+
+// the input files, each directory giving the xml files under it and each glob the files it matches,
+// sorted so the runs are reproducible
+pub fn input_files(inputs: &[String]) -> Result<Vec<String>, ::failure::Error> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        let mut matches = if path.exists() || !input.contains(['*', '?', '[']) {
+            vec![path.to_owned()]
+        } else {
+            ::glob::glob(input)
+                .map_err(|e| ::failure::err_msg(format!("invalid input glob {}: {}", input, e)))?
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        if matches.is_empty() {
+            warn!("No input matches {}", input);
+        }
+        matches.sort();
+        for path in matches {
+            if path.is_dir() {
+                let found = xml_files(&path)?;
+                if found.is_empty() {
+                    warn!("No xml file in {}", path.display());
+                }
+                files.extend(found);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    Ok(files.into_iter().map(|path| path.display().to_string()).collect())
+}
+
+fn xml_files(dir: &Path) -> Result<Vec<PathBuf>, ::failure::Error> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(|e| ::failure::err_msg(format!("cannot read {}: {}", dir.display(), e)))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    let mut files = Vec::new();
+    for path in entries {
+        if path.is_dir() {
+            files.extend(xml_files(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "xml") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+pub fn relative_to(dir: &Path, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_owned()
+    } else {
+        dir.join(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn dir(test: &str) -> PathBuf {
+        ::testing::temp_dir(&format!("config_{}", test))
+    }
+
+    #[test]
+    fn relative_paths() {
+        let dir = dir("relative_paths");
+        fs::write(dir.join("idl.toml"), r#"
+            inputs = ["packets/*.xml", "/abs/a.xml"]
+            include_dirs = ["common"]
+            cache = "build/idl.cache"
+            protocol_version = 3
+
+            [cpp]
+            output_header_folder = "include"
+            output_source_folder = "/abs/src"
+        "#).unwrap();
+        let config = Config::load(&dir.join("idl.toml")).unwrap();
+        assert_eq!(config.inputs, vec![dir.join("packets/*.xml").display().to_string(), "/abs/a.xml".to_owned()]);
+        assert_eq!(config.include_dirs, vec![dir.join("common")]);
+        assert_eq!(config.cache, Some(dir.join("build/idl.cache")));
+        assert_eq!(config.protocol_version, Some(3));
+        let cpp = config.cpp.unwrap();
+        assert!(cpp.enabled);
+        assert_eq!(cpp.output_header_folder, Some(dir.join("include").display().to_string()));
+        assert_eq!(cpp.output_source_folder, Some("/abs/src".to_owned()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid() {
+        let dir = dir("invalid");
+        fs::write(dir.join("idl.toml"), "input = [\"a.xml\"]\n").unwrap();
+        assert!(Config::load(&dir.join("idl.toml")).unwrap_err().to_string().starts_with(&dir.join("idl.toml").display().to_string()));
+        assert!(Config::load(Path::new("/nonexistent/idl.toml")).unwrap_err().to_string().starts_with("cannot read /nonexistent/idl.toml"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn inputs() {
+        let dir = dir("inputs");
+        for file in &["b.xml", "a.xml", "notes.txt", "sub/c.xml", "sub/deep/d.xml", "other/e.xml"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let path = |file: &str| dir.join(file).display().to_string();
+        let inputs = |inputs: &[String]| super::input_files(inputs).unwrap();
+        // directories are searched recursively, globs are expanded and both are sorted
+        assert_eq!(inputs(&[path("sub"), path("*.xml")]), vec![path("sub/c.xml"), path("sub/deep/d.xml"), path("a.xml"), path("b.xml")]);
+        // a file given twice is only generated once
+        assert_eq!(inputs(&[path("b.xml"), path("*.xml")]), vec![path("b.xml"), path("a.xml")]);
+        assert_eq!(inputs(&[path("*/e.xml")]), vec![path("other/e.xml")]);
+        assert!(inputs(&[path("*.json")]).is_empty());
+        // plain paths are kept even when missing, to be reported when opened
+        assert_eq!(inputs(&[path("missing.xml")]), vec![path("missing.xml")]);
+        assert!(super::input_files(&[path("[.xml")]).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
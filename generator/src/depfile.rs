@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+// the files generated from every input and the ones they were generated from, for build systems
+#[derive(Default)]
+pub struct Dependencies {
+    inputs: Vec<Input>
+}
+
+struct Input {
+    path: PathBuf,
+    name: String,
+    files: Vec<PathBuf>,
+    outputs: Vec<PathBuf>
+}
+
+impl Dependencies {
+    // `files` holds the input itself and everything it includes or imports
+    pub fn add(&mut self, input: &Path, files: Vec<PathBuf>, outputs: Vec<PathBuf>) {
+        let name = input.file_stem().map_or_else(|| input.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+        let outputs = outputs.iter().map(|output| output.canonicalize().unwrap_or_else(|_| output.clone())).collect();
+        self.inputs.push(Input { path: input.to_owned(), name, files, outputs });
+    }
+
+    // the shared types are generated from every input
+    pub fn add_shared(&mut self, outputs: Vec<PathBuf>) {
+        let files = self.all_files().into_iter().cloned().collect();
+        self.add(Path::new(::flat_ast::SHARED_TYPES_FILENAME), files, outputs);
+    }
+
+    fn all_files(&self) -> Vec<&PathBuf> {
+        let mut files = self.inputs.iter().flat_map(|input| input.files.iter()).collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    // a Makefile-style depfile, with a rule for every generated file
+    pub fn write_depfile(&self, path: &Path) -> Result<(), ::failure::Error> {
+        let mut contents = String::new();
+        for input in &self.inputs {
+            let files = input.files.iter().map(|file| make_escape(file)).collect::<Vec<_>>().join(" ");
+            for output in &input.outputs {
+                contents += &format!("{}: {}\n", make_escape(output), files);
+            }
+        }
+        ::codegen::write_if_changed(path, contents.as_bytes())
+    }
+
+    // a CMake script setting the outputs and dependencies of the whole run and of every input,
+    // as `IDL_OUTPUTS`/`IDL_DEPENDS` and `IDL_<INPUT>_OUTPUTS`/`IDL_<INPUT>_DEPENDS`
+    pub fn write_cmake(&self, path: &Path, version: &str) -> Result<(), ::failure::Error> {
+        let mut contents = format!("# Generated with IDL v{}\n", version);
+        // the types of an imported file are generated for every input importing it
+        let mut outputs = self.inputs.iter().flat_map(|input| input.outputs.iter()).collect::<Vec<_>>();
+        outputs.sort();
+        outputs.dedup();
+        contents += &cmake_list("IDL_OUTPUTS", &outputs);
+        contents += &cmake_list("IDL_DEPENDS", &self.all_files());
+        // the variables are named after the file stem, inputs sharing one would overwrite each other's
+        let mut names = ::std::collections::HashMap::new();
+        for input in &self.inputs {
+            let name = cmake_name(&input.name);
+            if let Some(other) = names.insert(name.clone(), &input.path) {
+                return Err(::failure::err_msg(format!("inputs {} and {} would both set IDL_{}_OUTPUTS, rename one of them",
+                    other.display(), input.path.display(), name)));
+            }
+            contents += &cmake_list(&format!("IDL_{}_OUTPUTS", name), &input.outputs.iter().collect::<Vec<_>>());
+            contents += &cmake_list(&format!("IDL_{}_DEPENDS", name), &input.files.iter().collect::<Vec<_>>());
+        }
+        ::codegen::write_if_changed(path, contents.as_bytes())
+    }
+}
+
+fn make_escape(path: &Path) -> String {
+    path.display().to_string().replace('$', "$$").replace(' ', "\\ ").replace('#', "\\#")
+}
+
+fn cmake_name(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect()
+}
+
+fn cmake_list(variable: &str, paths: &[&PathBuf]) -> String {
+    let mut list = format!("set({}\n", variable);
+    for path in paths {
+        list += &format!("    \"{}\"\n", path.display().to_string().replace('\\', "/").replace('"', "\\\""));
+    }
+    list + ")\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dependencies;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn escaping() {
+        assert_eq!(super::make_escape(Path::new("/src/my dir/a$b#1.xml")), "/src/my\\ dir/a$$b\\#1.xml");
+        assert_eq!(super::cmake_name("cli-login.v2"), "CLI_LOGIN_V2");
+        assert_eq!(super::cmake_list("IDL_OUTPUTS", &[&PathBuf::from("C:\\out\\\"a\".h")]), "set(IDL_OUTPUTS\n    \"C:/out/\\\"a\\\".h\"\n)\n");
+    }
+
+    #[test]
+    fn outputs() {
+        let dir = ::testing::temp_dir("depfile_outputs");
+        let mut dependencies = Dependencies::default();
+        let files = vec![PathBuf::from("/src/a b.xml"), PathBuf::from("/src/common.xml")];
+        dependencies.add(Path::new("a b.xml"), files, vec![PathBuf::from("/out/a.h"), PathBuf::from("/out/common_types.h")]);
+        dependencies.add(Path::new("c.xml"), vec![PathBuf::from("/src/c.xml")], vec![PathBuf::from("/out/c.h"), PathBuf::from("/out/common_types.h")]);
+        dependencies.write_depfile(&dir.join("idl.d")).unwrap();
+        assert_eq!(::std::fs::read_to_string(dir.join("idl.d")).unwrap(), "\
+/out/a.h: /src/a\\ b.xml /src/common.xml
+/out/common_types.h: /src/a\\ b.xml /src/common.xml
+/out/c.h: /src/c.xml
+/out/common_types.h: /src/c.xml
+");
+        dependencies.write_cmake(&dir.join("idl.cmake"), "1.0").unwrap();
+        let cmake = ::std::fs::read_to_string(dir.join("idl.cmake")).unwrap();
+        assert!(cmake.starts_with("# Generated with IDL v1.0\n"));
+        assert!(cmake.contains("set(IDL_OUTPUTS\n    \"/out/a.h\"\n    \"/out/c.h\"\n    \"/out/common_types.h\"\n)\n"));
+        assert!(cmake.contains("set(IDL_DEPENDS\n    \"/src/a b.xml\"\n    \"/src/c.xml\"\n    \"/src/common.xml\"\n)\n"));
+        assert!(cmake.contains("set(IDL_A_B_OUTPUTS\n    \"/out/a.h\"\n    \"/out/common_types.h\"\n)\n"));
+        assert!(cmake.contains("set(IDL_C_DEPENDS\n    \"/src/c.xml\"\n)\n"));
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn same_stem() {
+        let mut dependencies = Dependencies::default();
+        dependencies.add(Path::new("login/char-list.xml"), vec![PathBuf::from("/src/login/char-list.xml")], vec![PathBuf::from("/out/a.h")]);
+        dependencies.add(Path::new("world/char_list.xml"), vec![PathBuf::from("/src/world/char_list.xml")], vec![PathBuf::from("/out/b.h")]);
+        let dir = ::testing::temp_dir("depfile_same_stem");
+        let path = dir.join("idl.cmake");
+        assert_eq!(dependencies.write_cmake(&path, "1.0").unwrap_err().to_string(),
+            "inputs login/char-list.xml and world/char_list.xml would both set IDL_CHAR_LIST_OUTPUTS, rename one of them");
+        assert!(!path.exists());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+}
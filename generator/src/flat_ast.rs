@@ -1,37 +1,40 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Packet {
     type_: String,
     contents: Vec<PacketContent>,
     doc: Option<String>,
     class_name: String,
-    filename: String
+    filename: String,
+    presence: Option<String>,
+    direction: Direction
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PacketContent {
     Include(String, bool),
-    Element(Element),
+    Element(Box<Element>),
     Simple(SimpleType),
     Complex(ComplexType)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ComplexType {
     name: String,
     content: ComplexTypeContent,
     doc: Option<String>,
     anonymous: bool,
-    inline: bool
+    inline: bool,
+    origin: Option<Origin>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ComplexTypeContent {
     Seq(Sequence),
     Choice(Choice),
     Empty
 }
 
-pub use ::schema::ast::Occurs;
+pub use ::schema::ast::{Occurs, InnerOccurs, Condition, ConditionOp, Compute, ComputeKind, Scale, MapType, Direction};
 
 #[derive(Debug, Clone)]
 pub struct Sequence {
@@ -39,10 +42,11 @@ pub struct Sequence {
     doc: Option<String>,
     occurs: Option<Occurs>,
     size_occurs: Option<String>,
-    inline: bool
+    inline: bool,
+    presence: Option<String>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Choice {
     elements: Vec<Element>,
     doc: Option<String>,
@@ -67,7 +71,27 @@ pub struct Element {
     special_read_write: Option<String>,
     bits: Option<u32>,
     occur_is_defined: bool,
-    bitset: Option<Bitset>
+    bitset: Option<Bitset>,
+    optional: bool,
+    occurs_ref: Option<String>,
+    count_for: Option<String>,
+    inner_occurs: Vec<InnerOccurs>,
+    condition: Option<Condition>,
+    fixed: Option<String>,
+    padding: Option<String>,
+    compute: Option<Compute>,
+    compute_range: Vec<String>,
+    blob: bool,
+    blob_length: Option<String>,
+    embeds: Option<EmbeddedPacket>,
+    map: Option<MapType>
+}
+
+// the packet a blob holds, by the names of its generated class and file
+#[derive(Debug, Clone)]
+pub struct EmbeddedPacket {
+    pub class_name: String,
+    pub filename: String
 }
 
 #[derive(Debug, Clone)]
@@ -77,26 +101,29 @@ pub struct Bitset {
     pub name: String
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SimpleType {
     name: String,
     contents: Vec<SimpleTypeContent>,
-    doc: Option<String>
+    doc: Option<String>,
+    origin: Option<Origin>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SimpleTypeContent {
     Restriction(Restriction)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Restriction {
     base: String,
     doc: Option<String>,
-    contents: Vec<RestrictionContent>
+    contents: Vec<RestrictionContent>,
+    flags: bool,
+    scale: Option<Scale>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RestrictionContent {
     Enumeration(Enumeration),
     Length(u32),
@@ -104,7 +131,7 @@ pub enum RestrictionContent {
     MaxValue(String)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Enumeration {
     value: String,
     id: i64,
@@ -139,37 +166,100 @@ impl PacketContent {
             _ => false
         }
     }
+
+    // the shared file a type comes from, shared types are generated once in the common types files
+    pub fn origin(content: &PacketContent) -> Option<&Origin> {
+        match content {
+            PacketContent::Simple(s) => s.origin().as_ref(),
+            PacketContent::Complex(c) => c.origin().as_ref(),
+            _ => None
+        }
+    }
+}
+
+// the file a type shared between packets is defined in, and where it is generated once under its own name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub path: String,
+    pub namespace: String,
+    pub filename: String
+}
+
+impl Origin {
+    // the types of included files, with --shared-types
+    pub fn shared(path: String) -> Self {
+        Origin { path, namespace: SHARED_TYPES_NAMESPACE.to_owned(), filename: SHARED_TYPES_FILENAME.to_owned() }
+    }
+}
+
+// the class (a namespace really) and file name the shared types are generated in
+pub const SHARED_TYPES_NAMESPACE: &str = "Types";
+pub const SHARED_TYPES_FILENAME: &str = "packet_types";
+
+// the direction and the generated class and file names guessed from the prefix of a packet type,
+// used when the packet has no explicit `direction` or `className`
+pub fn packet_names(type_: &str) -> (Direction, String, String) {
+    use ::heck::*;
+    let prefix = type_.to_uppercase();
+    let direction = if prefix.starts_with("ISC_") {
+        Direction::Inter
+    } else if prefix.starts_with("PAKCS_") {
+        Direction::Client
+    } else {
+        Direction::Server
+    };
+    let class_name = direction_class_name(type_, direction);
+    let filename = class_name.to_snake_case();
+    (direction, class_name, filename)
+}
+
+// the class name of a packet with an explicit `direction`, its type without the leading prefix behind the direction's one
+pub fn direction_class_name(type_: &str, direction: Direction) -> String {
+    use ::heck::*;
+    let prefix = match direction {
+        Direction::Client => "Cli",
+        Direction::Server => "Srv",
+        Direction::Inter => "Isc"
+    };
+    let name = match type_.find('_') {
+        Some(idx) if idx + 1 < type_.len() => &type_[idx + 1..],
+        _ => type_
+    };
+    prefix.to_string() + &name.to_upper_camel_case()
 }
 
 impl Packet {
     pub fn new(type_: String
                , doc: Option<String>) -> Self {
-        use ::heck::*;
-        let name = type_.clone().to_lower_camel_case();
-        let (class_name, filename) = if name.starts_with("Isc") {
-            (name.clone(),
-             name.clone().to_snake_case())
-        } else {
-            if name.starts_with("Pakcs") {
-                let name = "Cli".to_string() + &name[5..];
-                (name.clone(),
-                 name.clone().to_snake_case())
-            } else {
-                let name = "Srv".to_string() + &name[5..];
-                (name.clone(),
-                 name.clone().to_snake_case())
-            }
-        };
+        let (direction, class_name, filename) = packet_names(&type_);
 
         Packet{
             type_,
             contents: Vec::new(),
             doc: doc,
             class_name: class_name,
-            filename: filename
+            filename: filename,
+            presence: None,
+            direction
         }
     }
 
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.direction = direction;
+    }
+
+    pub fn set_class_name(&mut self, class_name: String) {
+        self.class_name = class_name;
+    }
+
+    pub fn set_filename(&mut self, filename: String) {
+        self.filename = filename;
+    }
+
     pub fn add_content(&mut self, content: PacketContent) {
         self.contents.push(content);
     }
@@ -197,6 +287,14 @@ impl Packet {
     pub fn doc(&self) -> &Option<String> {
         &self.doc
     }
+
+    pub fn presence(&self) -> &Option<String> {
+        &self.presence
+    }
+
+    pub fn set_presence(&mut self, presence: String) {
+        self.presence = Some(presence);
+    }
 }
 
 impl ComplexType {
@@ -207,7 +305,7 @@ impl ComplexType {
         anonymous: bool,
         inline: bool
     ) -> Self {
-        ComplexType{ name, content, doc, anonymous, inline }
+        ComplexType{ name, content, doc, anonymous, inline, origin: None }
     }
 
     pub fn name(&self) -> &String {
@@ -235,6 +333,14 @@ impl ComplexType {
     pub fn inline(&self) -> bool {
         self.inline
     }
+
+    pub fn origin(&self) -> &Option<Origin> {
+        &self.origin
+    }
+
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.origin = Some(origin);
+    }
 }
 
 impl Sequence {
@@ -244,7 +350,7 @@ impl Sequence {
         doc: Option<String>,
         inline: bool
     ) -> Self {
-        Sequence{ elements: Vec::new(), occurs, size_occurs, doc, inline }
+        Sequence{ elements: Vec::new(), occurs, size_occurs, doc, inline, presence: None }
     }
 
     pub fn add_element(&mut self, element: Element) {
@@ -263,14 +369,21 @@ impl Sequence {
         &self.doc
     }
 
+    #[allow(dead_code)]
     pub fn occurs(&self) -> &Option<Occurs> {
         &self.occurs
     }
 
+    #[allow(dead_code)]
     pub fn size_occurs(&self) -> &Option<String> {
         &self.size_occurs
     }
 
+    // the repetition moves to the elements of this sequence's type
+    pub fn take_occurs(&mut self) -> (Option<Occurs>, Option<String>) {
+        (self.occurs.take(), self.size_occurs.take())
+    }
+
     pub fn inline(&self) -> bool {
         self.inline
     }
@@ -279,6 +392,14 @@ impl Sequence {
     pub fn set_inline(&mut self, inline: bool) {
         self.inline = inline;
     }
+
+    pub fn presence(&self) -> &Option<String> {
+        &self.presence
+    }
+
+    pub fn set_presence(&mut self, presence: String) {
+        self.presence = Some(presence);
+    }
 }
 
 impl Choice {
@@ -335,7 +456,11 @@ impl Element {
         Element{ name, init, type_, id, occurs, size_occurs, doc
                  , anonymous, reference, enum_type: None,
                  is_defined: false, special_read_write, bits,
-                 occur_is_defined: false, bitset }
+                 occur_is_defined: false, bitset, optional: false,
+                 occurs_ref: None, count_for: None, inner_occurs: Vec::new(),
+                 condition: None, fixed: None, padding: None,
+                 compute: None, compute_range: Vec::new(), blob: false, blob_length: None, embeds: None,
+                 map: None }
     }
     
     pub fn name(&self) -> &String {
@@ -428,6 +553,120 @@ impl Element {
     pub fn occur_is_defined(&self) -> bool {
         self.occur_is_defined
     }
+
+    pub fn set_optional(&mut self, optional: bool) {
+        self.optional = optional;
+    }
+
+    pub fn optional(&self) -> bool {
+        self.optional
+    }
+
+    pub fn set_occurs_ref(&mut self, occurs_ref: String) {
+        self.occurs_ref = Some(occurs_ref);
+    }
+
+    pub fn occurs_ref(&self) -> &Option<String> {
+        &self.occurs_ref
+    }
+
+    pub fn set_count_for(&mut self, count_for: String) {
+        self.count_for = Some(count_for);
+    }
+
+    pub fn count_for(&self) -> &Option<String> {
+        &self.count_for
+    }
+
+    pub fn set_inner_occurs(&mut self, inner_occurs: Vec<InnerOccurs>) {
+        self.inner_occurs = inner_occurs;
+    }
+
+    pub fn inner_occurs(&self) -> &[InnerOccurs] {
+        &self.inner_occurs
+    }
+
+    // repeats the element once more, as the innermost level
+    pub fn add_level(&mut self, occurs: Occurs, size_occurs: Option<String>) {
+        if self.occurs.is_none() {
+            self.occurs = Some(occurs);
+            self.size_occurs = size_occurs;
+        } else {
+            self.inner_occurs.push(InnerOccurs { occurs, size_occurs });
+        }
+    }
+
+    pub fn set_condition(&mut self, condition: Condition) {
+        self.condition = Some(condition);
+    }
+
+    pub fn condition(&self) -> &Option<Condition> {
+        &self.condition
+    }
+
+    pub fn set_fixed(&mut self, fixed: String) {
+        self.fixed = Some(fixed);
+    }
+
+    pub fn fixed(&self) -> &Option<String> {
+        &self.fixed
+    }
+
+    pub fn set_padding(&mut self, fill: String) {
+        self.padding = Some(fill);
+    }
+
+    pub fn padding(&self) -> &Option<String> {
+        &self.padding
+    }
+
+    pub fn set_compute(&mut self, compute: Compute) {
+        self.compute = Some(compute);
+    }
+
+    pub fn compute(&self) -> &Option<Compute> {
+        &self.compute
+    }
+
+    pub fn set_compute_range(&mut self, range: Vec<String>) {
+        self.compute_range = range;
+    }
+
+    pub fn compute_range(&self) -> &[String] {
+        &self.compute_range
+    }
+
+    pub fn set_blob(&mut self, blob: bool) {
+        self.blob = blob;
+    }
+
+    pub fn blob(&self) -> bool {
+        self.blob
+    }
+
+    pub fn set_blob_length(&mut self, length: String) {
+        self.blob_length = Some(length);
+    }
+
+    pub fn blob_length(&self) -> &Option<String> {
+        &self.blob_length
+    }
+
+    pub fn set_embeds(&mut self, embeds: EmbeddedPacket) {
+        self.embeds = Some(embeds);
+    }
+
+    pub fn embeds(&self) -> &Option<EmbeddedPacket> {
+        &self.embeds
+    }
+
+    pub fn set_map(&mut self, map: MapType) {
+        self.map = Some(map);
+    }
+
+    pub fn map(&self) -> &Option<MapType> {
+        &self.map
+    }
 }
 
 impl Bitset {
@@ -439,7 +678,7 @@ impl Bitset {
 impl SimpleType {
     pub fn new(name: String, doc: Option<String>) -> Self {
         use heck::ToLowerCamelCase;
-        SimpleType{ name: name.to_lower_camel_case(), contents: Vec::new(), doc }
+        SimpleType{ name: name.to_lower_camel_case(), contents: Vec::new(), doc, origin: None }
     }
 
     pub fn add_content(&mut self, content: SimpleTypeContent) {
@@ -457,11 +696,19 @@ impl SimpleType {
     pub fn doc(&self) -> &Option<String> {
         &self.doc
     }
+
+    pub fn origin(&self) -> &Option<Origin> {
+        &self.origin
+    }
+
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.origin = Some(origin);
+    }
 }
 
 impl Restriction {
     pub fn new(base: String, doc: Option<String> ) -> Self {
-        Restriction{ base, contents: Vec::new(), doc }
+        Restriction{ base, contents: Vec::new(), doc, flags: false, scale: None }
     }
 
     pub fn add_content(&mut self, content: RestrictionContent) {
@@ -479,6 +726,22 @@ impl Restriction {
     pub fn doc(&self) -> &Option<String> {
         &self.doc
     }
+
+    pub fn set_flags(&mut self, flags: bool) {
+        self.flags = flags;
+    }
+
+    pub fn flags(&self) -> bool {
+        self.flags
+    }
+
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = Some(scale);
+    }
+
+    pub fn scale(&self) -> &Option<Scale> {
+        &self.scale
+    }
 }
 
 
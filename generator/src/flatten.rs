@@ -2,7 +2,62 @@ use ::schema::ast;
 use ::flat_ast;
 use ::schema::Reader;
 use std::fs::File;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// a file brought in by `<import>`, its types are only flattened into the packet once referenced
+struct ImportedFile {
+    packet: Arc<ast::Packet>,
+    // the aliases of the file's own imports, only visible inside it
+    imports: HashMap<String, PathBuf>
+}
+
+// the included and imported files parsed so far, shared by all the packets of a run
+#[derive(Default)]
+pub struct IncludeCache {
+    files: Mutex<HashMap<PathBuf, Arc<ast::Packet>>>
+}
+
+impl IncludeCache {
+    fn load(&self, path: &Path) -> Result<Arc<ast::Packet>, ::failure::Error> {
+        let key = canonical(path);
+        if let Some(packet) = self.files.lock().unwrap().get(&key) {
+            trace!("{} already parsed", path.display());
+            return Ok(packet.clone());
+        }
+        // parsed outside of the lock, at worst two threads parse the same file at once
+        let packet = Arc::new(Reader::load_packet(File::open(path)?)?);
+        Ok(self.files.lock().unwrap().entry(key).or_insert(packet).clone())
+    }
+}
+
+// what is kept of the schema and where its files are looked for
+pub struct Options<'a> {
+    pub version: Option<u32>,
+    pub profiles: &'a [String],
+    pub shared: bool,
+    pub include_dirs: &'a [PathBuf],
+    pub cache: &'a IncludeCache,
+    pub packets: &'a PacketNames
+}
+
+// the class name, file name and input of every packet of the run by type, to resolve the ones embedded in others
+pub type PacketNames = HashMap<String, (String, String, PathBuf)>;
+
+// the names of the generated class and file, explicit or from the direction, the type prefix otherwise
+pub fn packet_names(p: &ast::Packet) -> (String, String) {
+    let (_, class_name, filename) = flat_ast::packet_names(p.type_());
+    let (class_name, filename) = match p.class_name().clone().or_else(|| p.direction().map(|direction| flat_ast::direction_class_name(p.type_(), direction))) {
+        Some(class_name) => {
+            use ::heck::ToSnakeCase;
+            let filename = class_name.to_snake_case();
+            (class_name, filename)
+        },
+        None => (class_name, filename)
+    };
+    (class_name, p.file_name().clone().unwrap_or(filename))
+}
 
 struct Context<'a> {
     packet: &'a mut flat_ast::Packet,
@@ -10,14 +65,47 @@ struct Context<'a> {
     complex_types: HashSet<String>,
     is_in_choice: bool,
     bitsets: u32,
-    current_bitset: Option<u32>
+    current_bitset: Option<u32>,
+    version: Option<u32>,
+    profiles: &'a [String],
+    // the aliases visible in the file being flattened, by the path they import
+    imports: HashMap<String, PathBuf>,
+    // every file imported so far, directly or by another import
+    import_files: HashMap<PathBuf, ImportedFile>,
+    imported: HashMap<String, String>,
+    // the alias and path of the imported file whose type is being flattened
+    scope: Option<(String, PathBuf)>,
+    shared: bool,
+    origin: Option<flat_ast::Origin>,
+    // the imported file whose own output is being flattened, its types are defined there
+    output: Option<PathBuf>,
+    include_dirs: &'a [PathBuf],
+    cache: &'a IncludeCache,
+    packets: &'a PacketNames,
+    embedded: HashSet<PathBuf>,
+    includes: Vec<PathBuf>,
+    included: HashSet<PathBuf>,
+    // plain type names along with the aliases visible where they are used
+    unqualified: Vec<(String, Vec<(String, PathBuf)>)>,
+    // qualified type names whose prefix is not an alias of the file using them
+    foreign: Vec<String>,
+    // every alias of every file flattened or imported
+    aliases: HashSet<String>,
+    errors: Vec<String>
 }
 
 impl<'a> Context<'a> {
-    fn add_content(&mut self, content: flat_ast::PacketContent) {
+    fn add_content(&mut self, mut content: flat_ast::PacketContent) {
         if let flat_ast::PacketContent::Complex(ref cot) = content {
             self.complex_types.insert(cot.name().clone());
         }
+        if let Some(ref origin) = self.origin {
+            match content {
+                flat_ast::PacketContent::Simple(ref mut s) => s.set_origin(origin.clone()),
+                flat_ast::PacketContent::Complex(ref mut c) => c.set_origin(origin.clone()),
+                _ => {}
+            }
+        }
         self.packet.add_content(content);
     }
 
@@ -65,7 +153,7 @@ impl<'a> Context<'a> {
                 Element(e) => {
                     if let Some(bitset) = e.bitset() {
                         if bitset.name == id {
-                            res.push(e);
+                            res.push(&mut **e);
                         }
                     }
                 },
@@ -108,6 +196,170 @@ impl<'a> Context<'a> {
         Some(0)
     }
 
+    // a file is looked for next to the one referencing it first, then in the include directories
+    fn locate(&self, search_path: &Path, location: &str) -> Result<PathBuf, ::failure::Error> {
+        ::std::iter::once(search_path).chain(self.include_dirs.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(location))
+            .find(|path| path.is_file())
+            .ok_or_else(|| ::failure::err_msg(format!("{} not found in {} or the include directories", location, search_path.display())))
+    }
+
+    fn add_import(&mut self, search_path: &Path, import: &ast::Import) -> Result<(), ::failure::Error> {
+        let path = canonical(&self.locate(search_path, &import.path)?);
+        add_alias(&mut self.imports, import, &path)?;
+        self.aliases.insert(import.alias.clone());
+        self.load_import(&path)
+    }
+
+    fn load_import(&mut self, path: &Path) -> Result<(), ::failure::Error> {
+        if self.import_files.contains_key(path) {
+            return Ok(());
+        }
+        debug!("Importing {}", path.display());
+        let packet = self.cache.load(path)?;
+        let dir = path.parent().unwrap_or(Path::new("./")).to_owned();
+        let mut imports = HashMap::new();
+        for content in packet.contents() {
+            if let ast::PacketContent::Import(ref import) = content {
+                add_alias(&mut imports, import, &canonical(&self.locate(&dir, &import.path)?))?;
+                self.aliases.insert(import.alias.clone());
+            }
+        }
+        let nested = imports.values().cloned().collect::<Vec<_>>();
+        self.import_files.insert(path.to_owned(), ImportedFile { packet, imports });
+        for path in nested {
+            self.load_import(&path)?;
+        }
+        Ok(())
+    }
+
+    fn import_defines(&self, path: &Path, name: &str) -> bool {
+        self.import_files[path].packet.contents().iter().any(|content| match content {
+            ast::PacketContent::SimpleType(ref simple) => simple.name() == name,
+            ast::PacketContent::ComplexType(ref complex) => complex.name() == name,
+            _ => false
+        })
+    }
+
+    // the type an element refers to: `alias::Type`, or a plain name used inside an imported file, is flattened
+    // into the packet once and generated in the output of its file, anything else is left as it is
+    fn resolve_type(&mut self, type_: &str) -> String {
+        let (alias, path, name) = match type_.find("::") {
+            Some(idx) => match self.imports.get(&type_[..idx]) {
+                Some(path) => (type_[..idx].to_owned(), path.clone(), type_[idx + 2..].to_owned()),
+                None => {
+                    self.foreign.push(type_.to_owned());
+                    return type_.to_owned();
+                }
+            },
+            None => match self.scope.clone() {
+                Some((alias, path)) if self.import_defines(&path, type_) => (alias, path, type_.to_owned()),
+                Some(_) => return type_.to_owned(),
+                None => {
+                    let mut aliases = self.imports.iter().map(|(alias, path)| (alias.clone(), path.clone())).collect::<Vec<_>>();
+                    aliases.sort();
+                    self.unqualified.push((type_.to_owned(), aliases));
+                    return type_.to_owned();
+                }
+            }
+        };
+        // a file may be imported under several aliases, its types are still flattened once
+        let key = format!("{}::{}", path.display(), name);
+        if let Some(qualified) = self.imported.get(&key) {
+            return qualified.clone();
+        }
+        // imported types are generated once for every packet, so they keep their own name
+        let qualified = {
+            use ::heck::ToLowerCamelCase;
+            name.to_lower_camel_case()
+        };
+        let (version, profiles) = (self.version, self.profiles);
+        let definition = self.import_files[&path].packet.contents().iter().find(|content| match content {
+            ast::PacketContent::SimpleType(ref simple) => *simple.name() == name && simple.availability().includes(version, profiles),
+            ast::PacketContent::ComplexType(ref complex) => *complex.name() == name && complex.availability().includes(version, profiles),
+            _ => false
+        }).cloned();
+        let definition = match definition {
+            Some(definition) => definition,
+            None => {
+                self.errors.push(format!("type {} not found in import {} ({})", name, alias, path.display()));
+                return type_.to_owned();
+            }
+        };
+        self.imported.insert(key, qualified.clone());
+        let origin = if self.output.as_ref() == Some(&path) {
+            None
+        } else {
+            Some(import_origin(&path))
+        };
+        let origin = ::std::mem::replace(&mut self.origin, origin);
+        // the type is resolved in the file defining it, with that file's own imports
+        let imports = ::std::mem::replace(&mut self.imports, self.import_files[&path].imports.clone());
+        let scope = self.scope.replace((alias, path));
+        let path = ::std::mem::replace(&mut self.path, vec![qualified.clone()]);
+        match definition {
+            ast::PacketContent::SimpleType(ref simple) => match flatten_simple(simple, qualified.clone()) {
+                Ok(simple) => self.add_content(flat_ast::PacketContent::Simple(simple)),
+                Err(e) => self.errors.push(e.to_string())
+            },
+            ast::PacketContent::ComplexType(ref complex) => flatten_complex(complex, qualified.clone(), self),
+            _ => {}
+        }
+        self.scope = scope;
+        self.imports = imports;
+        self.path = path;
+        self.origin = origin;
+        qualified
+    }
+
+    // plain names that are not defined in the packet but in one of its imports have to be qualified
+    fn check_unqualified(&mut self) {
+        let imported = self.imported.values().collect::<HashSet<_>>();
+        let defined = self.packet.contents().iter().filter_map(flat_ast::PacketContent::type_from_name)
+            .filter(|name| !imported.contains(name)).collect::<HashSet<_>>();
+        for (name, aliases) in ::std::mem::take(&mut self.unqualified) {
+            use ::heck::ToLowerCamelCase;
+            if defined.contains(&name) || defined.contains(&name.to_lower_camel_case()) {
+                continue;
+            }
+            let found = aliases.iter().filter(|(_, path)| self.import_defines(path, &name)).map(|(alias, _)| alias).collect::<Vec<_>>();
+            match found.len() {
+                0 => {},
+                1 => self.errors.push(format!("type {0} is not defined in the packet, use {1}::{0}", name, found[0])),
+                _ => self.errors.push(format!("type {} is ambiguous between the imports {}", name,
+                    found.iter().map(|alias| alias.as_str()).collect::<Vec<_>>().join(", ")))
+            }
+        }
+    }
+
+    // an alias only names a file inside the file importing it, not in the files including or importing that one
+    fn check_aliases(&mut self) {
+        for type_ in ::std::mem::take(&mut self.foreign) {
+            let alias = &type_[..type_.find("::").unwrap()];
+            if self.aliases.contains(alias) {
+                self.errors.push(format!("type {} uses the alias {} which is not imported by the file using it", type_, alias));
+            }
+        }
+    }
+
+    // shared and imported types keep their own name, so they may clash with the packet's or another file's ones
+    fn check_duplicates(&mut self) {
+        let mut origins = HashMap::new();
+        for content in self.packet.contents() {
+            if let Some(name) = flat_ast::PacketContent::type_from_name(content) {
+                let origin = flat_ast::PacketContent::origin(content).cloned();
+                match origins.get(&name) {
+                    Some(other) if *other != origin => {
+                        let describe = |origin: &Option<flat_ast::Origin>| origin.as_ref().map_or_else(|| "the packet".to_owned(), |origin| origin.path.clone());
+                        self.errors.push(format!("type {} is defined in both {} and {}", name, describe(other), describe(&origin)));
+                    },
+                    Some(_) => {},
+                    None => { origins.insert(name, origin); }
+                }
+            }
+        }
+    }
+
     fn stop_bits(&mut self) {
         if let Some(bitset) = self.current_bitset {
             if bitset % 8 != 0 {
@@ -123,26 +375,140 @@ impl<'a> Context<'a> {
     }
 }
 
-pub fn flatten(search_path: &::std::path::Path, p: &ast::Packet) -> Result<flat_ast::Packet, ::failure::Error> {
+// gives back the packet along with the files it includes or imports
+pub fn flatten(filename: &Path, p: &ast::Packet, options: &Options) -> Result<(flat_ast::Packet, Vec<PathBuf>), ::failure::Error> {
+    let search_path = match filename.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new(".")
+    };
     let mut packet = flat_ast::Packet::new(p.type_().clone(), p.doc().clone());
-    {
+    if let Some(direction) = p.direction() {
+        packet.set_direction(direction);
+    }
+    let (class_name, file_name) = packet_names(p);
+    packet.set_class_name(class_name);
+    packet.set_filename(file_name);
+    if let Some(ref presence) = p.presence() {
+        packet.set_presence(presence.clone());
+    }
+    let dependencies = {
         let mut ctx = Context {
             packet: &mut packet,
             path: Vec::new(),
             complex_types: HashSet::new(),
             is_in_choice: false,
             bitsets: 0,
-            current_bitset: None
+            current_bitset: None,
+            version: options.version,
+            profiles: options.profiles,
+            imports: HashMap::new(),
+            import_files: HashMap::new(),
+            imported: HashMap::new(),
+            scope: None,
+            shared: options.shared,
+            origin: None,
+            output: None,
+            include_dirs: options.include_dirs,
+            cache: options.cache,
+            packets: options.packets,
+            embedded: HashSet::new(),
+            includes: vec![canonical(filename)],
+            included: HashSet::new(),
+            unqualified: Vec::new(),
+            foreign: Vec::new(),
+            aliases: HashSet::new(),
+            errors: Vec::new()
         };
         flatten_(search_path, p, &mut ctx)?;
+        ctx.check_unqualified();
+        ctx.check_aliases();
+        ctx.check_duplicates();
+        if !ctx.errors.is_empty() {
+            return Err(::failure::err_msg(ctx.errors.join("\n")));
+        }
         if ctx.bitsets != 0 {
             ctx.add_content(flat_ast::PacketContent::Include("bitset".to_owned(), true));
         }
+        repeat_types(ctx.packet);
+        let mut dependencies = ctx.included.iter().cloned()
+            .chain(ctx.import_files.keys().cloned())
+            .chain(ctx.embedded.iter().cloned())
+            .collect::<Vec<_>>();
+        dependencies.sort();
+        dependencies
+    };
+    Ok((packet, dependencies))
+}
+
+// the types of an imported file, for its own output, along with the files it imports
+pub fn flatten_import(path: &Path, options: &Options) -> Result<(flat_ast::Packet, Vec<PathBuf>), ::failure::Error> {
+    let origin = import_origin(path);
+    let mut packet = flat_ast::Packet::new(origin.namespace.clone(), None);
+    packet.set_class_name(origin.namespace.clone());
+    packet.set_filename(origin.filename.clone());
+    let mut ctx = Context {
+        packet: &mut packet,
+        path: Vec::new(),
+        complex_types: HashSet::new(),
+        is_in_choice: false,
+        bitsets: 0,
+        current_bitset: None,
+        version: options.version,
+        profiles: options.profiles,
+        imports: HashMap::new(),
+        import_files: HashMap::new(),
+        imported: HashMap::new(),
+        scope: None,
+        shared: options.shared,
+        origin: None,
+        output: Some(canonical(path)),
+        include_dirs: options.include_dirs,
+        cache: options.cache,
+        packets: options.packets,
+        embedded: HashSet::new(),
+        includes: vec![canonical(path)],
+        included: HashSet::new(),
+        unqualified: Vec::new(),
+        foreign: Vec::new(),
+        aliases: HashSet::new(),
+        errors: Vec::new()
+    };
+    // the file is imported under its own name, as if a packet referenced every type it defines
+    let alias = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    ctx.add_import(Path::new("."), &ast::Import { path: origin.path.clone(), alias: alias.clone() })?;
+    let (version, profiles) = (ctx.version, ctx.profiles);
+    let names = ctx.import_files[&ctx.imports[&alias]].packet.contents().iter().filter_map(|content| match content {
+        ast::PacketContent::SimpleType(ref simple) if simple.availability().includes(version, profiles) => Some(simple.name().clone()),
+        ast::PacketContent::ComplexType(ref complex) if complex.availability().includes(version, profiles) => Some(complex.name().clone()),
+        _ => None
+    }).collect::<Vec<_>>();
+    for name in names {
+        ctx.resolve_type(&format!("{}::{}", alias, name));
+    }
+    ctx.check_aliases();
+    ctx.check_duplicates();
+    if !ctx.errors.is_empty() {
+        return Err(::failure::err_msg(ctx.errors.join("\n")));
+    }
+    if ctx.bitsets != 0 {
+        ctx.add_content(flat_ast::PacketContent::Include("bitset".to_owned(), true));
     }
-    Ok(packet)
+    repeat_types(ctx.packet);
+    let mut dependencies = ctx.import_files.keys().cloned().collect::<Vec<_>>();
+    dependencies.sort();
+    dependencies.dedup();
+    Ok((packet, dependencies))
 }
 
 fn flatten_(search_path: &::std::path::Path, packet: &ast::Packet, ctx: &mut Context) -> Result<(), ::failure::Error> {
+    // the aliases of a file are its own, an included file neither sees nor leaks them
+    let imports = ::std::mem::take(&mut ctx.imports);
+    // imports are registered up front so types can be referenced before their import
+    for content in packet.contents() {
+        if let ast::PacketContent::Import(ref import) = content {
+            ctx.add_import(search_path, import)?;
+        }
+    }
     for content in packet.contents() {
         use flat_ast::PacketContent::*;
         match content {
@@ -150,49 +516,89 @@ fn flatten_(search_path: &::std::path::Path, packet: &ast::Packet, ctx: &mut Con
                 ctx.add_content(Include(path.clone(), *system));
             },
             ast::PacketContent::IncludeXml(ref location) => {
-                let filenm = search_path.join(::std::path::Path::new(location));
+                let filenm = ctx.locate(search_path, location)?;
+                let path = canonical(&filenm);
+                if ctx.includes.contains(&path) {
+                    let chain = ctx.includes.iter().chain(::std::iter::once(&path))
+                        .map(|path| path.display().to_string()).collect::<Vec<_>>();
+                    return Err(::failure::err_msg(format!("include cycle: {}", chain.join(" -> "))));
+                }
+                if !ctx.included.insert(path.clone()) {
+                    debug!("Skipping {}, already included", filenm.display());
+                    continue;
+                }
                 debug!("Including {}", filenm.to_str().unwrap());
-                let file = File::open(&filenm)?;
-                let packet = Reader::load_packet(file)?;
-                flatten_(search_path, &packet, ctx)?;
+                let packet = ctx.cache.load(&filenm)?;
+                let origin = if ctx.shared {
+                    Some(flat_ast::Origin::shared(origin_name(&filenm)))
+                } else {
+                    ctx.origin.clone()
+                };
+                let origin = ::std::mem::replace(&mut ctx.origin, origin);
+                // the includes and imports of an included file are relative to that file
+                let dir = filenm.parent().unwrap_or(Path::new("./")).to_owned();
+                ctx.includes.push(path);
+                flatten_(&dir, &packet, ctx)?;
+                ctx.includes.pop();
+                ctx.origin = origin;
             },
+            ast::PacketContent::Import(_) => {},
+            ast::PacketContent::SimpleType(ref simple) if !simple.availability().includes(ctx.version, ctx.profiles) => {},
+            ast::PacketContent::ComplexType(ref complex) if !complex.availability().includes(ctx.version, ctx.profiles) => {},
+            ast::PacketContent::Element(ref element) if !element.availability().includes(ctx.version, ctx.profiles) => {},
+            ast::PacketContent::Padding(ref padding) if !padding.availability().includes(ctx.version, ctx.profiles) => {},
             ast::PacketContent::SimpleType(ref simple) => {
-                let simple = flatten_simple(simple);
+                let simple = flatten_simple(simple, simple.name().clone())?;
                 ctx.add_content(Simple(simple));
             },
             ast::PacketContent::ComplexType(ref complex) => {
                 ctx.path = vec![complex.name().clone()];
-                let _complex = flatten_complex(complex, ctx);
+                flatten_complex(complex, complex.name().clone(), ctx);
             },
             ast::PacketContent::Element(ref element) => {
                 let element = flatten_element(element, ctx, 0);
-                ctx.add_content(Element(element));
+                ctx.add_content(Element(Box::new(element)));
+            },
+            ast::PacketContent::Padding(ref padding) => {
+                let element = flatten_padding(padding, ctx, 0);
+                ctx.add_content(Element(Box::new(element)));
             }
         }
     }
     ctx.stop_bits();
+    ctx.imports = imports;
     Ok(())
 }
 
-fn flatten_simple(simple: &ast::SimpleType) -> flat_ast::SimpleType {
-    let mut type_ = flat_ast::SimpleType::new(simple.name().clone(), simple.doc().clone());
+fn flatten_simple(simple: &ast::SimpleType, name: String) -> Result<flat_ast::SimpleType, ::failure::Error> {
+    let mut type_ = flat_ast::SimpleType::new(name, simple.doc().clone());
     let mut enum_id = 0i64;
     for content in simple.contents() {
         match content {
             ast::SimpleTypeContent::Restriction(ref restriction) => {
-                let restrict = flatten_restriction(restriction, &mut enum_id);
+                let restrict = flatten_restriction(restriction, &mut enum_id)
+                    .map_err(|e| ::failure::err_msg(format!("type {}: {}", type_.name(), e)))?;
                 type_.add_content(flat_ast::SimpleTypeContent::Restriction(restrict));
             }
         }
     }
-    type_
+    Ok(type_)
 }
 
-fn flatten_restriction(r: &ast::Restriction, enum_id: &mut i64) -> flat_ast::Restriction {
+fn flatten_restriction(r: &ast::Restriction, enum_id: &mut i64) -> Result<flat_ast::Restriction, ::failure::Error> {
     let mut res = flat_ast::Restriction::new(r.base().clone(), r.doc().clone());
+    res.set_flags(r.flags());
+    if let Some(ref scale) = r.scale() {
+        res.set_scale(scale.clone());
+    }
+    let mut next_bit = 0;
     use self::ast::RestrictionContent::*;
     for content in r.contents() {
         let content = match content {
+            Enumeration(ref e) if r.flags() => {
+                let enm = flatten_flag(e, r.base(), &mut next_bit)?;
+                flat_ast::RestrictionContent::Enumeration(enm)
+            },
             Enumeration(ref e) => {
                 let enm = flatten_enum(e, enum_id);
                 flat_ast::RestrictionContent::Enumeration(enm)
@@ -203,7 +609,7 @@ fn flatten_restriction(r: &ast::Restriction, enum_id: &mut i64) -> flat_ast::Res
         };
         res.add_content(content);
     }
-    res
+    Ok(res)
 }
 
 fn flatten_enum(e: &ast::Enumeration, enum_id: &mut i64) -> flat_ast::Enumeration {
@@ -215,7 +621,38 @@ fn flatten_enum(e: &ast::Enumeration, enum_id: &mut i64) -> flat_ast::Enumeratio
     flat_ast::Enumeration::new(e.value().clone(), *enum_id - 1, e.doc().clone())
 }
 
-fn flatten_complex(c: &ast::ComplexType, ctx: &mut Context) {
+// flags are either a bit position, an explicit mask in `id` or the bit following the previous flag
+fn flatten_flag(e: &ast::Enumeration, base: &str, next_bit: &mut u32) -> Result<flat_ast::Enumeration, ::failure::Error> {
+    let width = base_width(base);
+    let bit = match (e.bit(), e.id()) {
+        (Some(bit), _) => bit,
+        (None, Some(mask)) => {
+            *next_bit = 64 - (*mask as u64).leading_zeros();
+            if *next_bit > width {
+                return Err(::failure::err_msg(format!("flag {} has the mask {:#x}, wider than its base {}", e.value(), mask, base)));
+            }
+            return Ok(flat_ast::Enumeration::new(e.value().clone(), *mask, e.doc().clone()));
+        },
+        (None, None) => *next_bit
+    };
+    if bit >= width {
+        return Err(::failure::err_msg(format!("flag {} is bit {}, outside of its {} bits base {}", e.value(), bit, width, base)));
+    }
+    *next_bit = bit + 1;
+    Ok(flat_ast::Enumeration::new(e.value().clone(), 1i64 << bit, e.doc().clone()))
+}
+
+// the number of bits of an integer base, types that are not one of the fixed width integers get the widest flags
+fn base_width(base: &str) -> u32 {
+    match base.trim_start_matches("std::") {
+        "uint8_t" | "int8_t" | "char" => 8,
+        "uint16_t" | "int16_t" => 16,
+        "uint32_t" | "int32_t" => 32,
+        _ => 64
+    }
+}
+
+fn flatten_complex(c: &ast::ComplexType, name: String, ctx: &mut Context) {
     use flat_ast::ComplexTypeContent::*;
     use self::ast::ComplexTypeContent;
     let mut inline = false;
@@ -228,7 +665,7 @@ fn flatten_complex(c: &ast::ComplexType, ctx: &mut Context) {
         },
         ComplexTypeContent::Empty => Empty
     };
-    let cot = flat_ast::ComplexType::new(c.name().clone(), content, c.doc().clone(), false, inline);
+    let cot = flat_ast::ComplexType::new(name, content, c.doc().clone(), false, inline);
     ctx.add_content(flat_ast::PacketContent::Complex(cot));
     ctx.stop_bits();
 }
@@ -272,8 +709,12 @@ fn flatten_anon_complex(c: &ast::AnonComplexType, ctx: &mut Context, element_nam
 
 fn flatten_seq(s: &ast::Sequence, ctx: &mut Context) -> flat_ast::Sequence {
     let mut seq = flat_ast::Sequence::new(s.occurs().clone(), s.size_occurs().clone(), s.doc().clone(), s.inline());
+    if let Some(ref presence) = s.presence() {
+        seq.set_presence(presence.clone());
+    }
     let mut max_id = 0;
-    for content in s.contents() {
+    let (version, profiles) = (ctx.version, ctx.profiles);
+    for content in s.contents().iter().filter(|content| is_included(content, version, profiles)) {
         let element = flatten_seq_content(content, ctx, max_id);
         if max_id <= element.id() {
             max_id = element.id() + 1;
@@ -287,7 +728,8 @@ fn flatten_choice(c: &ast::Choice, ctx: &mut Context) -> flat_ast::Choice {
     let mut choice = flat_ast::Choice::new(c.occurs().clone(), c.size_occurs().clone(), c.doc().clone());
     let mut max_id = 0;
     ctx.is_in_choice = true;
-    for content in c.contents() {
+    let (version, profiles) = (ctx.version, ctx.profiles);
+    for content in c.contents().iter().filter(|content| is_included(content, version, profiles)) {
         let element = flatten_seq_content(content, ctx, max_id);
         if max_id <= element.id() {
             max_id = element.id() + 1;
@@ -298,11 +740,87 @@ fn flatten_choice(c: &ast::Choice, ctx: &mut Context) -> flat_ast::Choice {
     choice
 }
 
+// a complex type whose sequence is repeated is a list of records, the elements of that type are repeated instead,
+// one level deeper than their own occurs
+fn repeat_types(packet: &mut flat_ast::Packet) {
+    let mut repeated = HashMap::new();
+    for content in packet.contents_mut() {
+        if let flat_ast::PacketContent::Complex(ref mut complex) = content {
+            let name = complex.name().clone();
+            if let flat_ast::ComplexTypeContent::Seq(ref mut seq) = complex.content_mut() {
+                if let (Some(occurs), size_occurs) = seq.take_occurs() {
+                    repeated.insert(name, (occurs, size_occurs));
+                }
+            }
+        }
+    }
+    if repeated.is_empty() {
+        return;
+    }
+    let repeat = |elem: &mut flat_ast::Element| if let Some((occurs, size_occurs)) = repeated.get(elem.type_()) {
+        elem.add_level(occurs.clone(), size_occurs.clone());
+    };
+    for content in packet.contents_mut() {
+        match content {
+            flat_ast::PacketContent::Element(ref mut elem) => repeat(elem),
+            flat_ast::PacketContent::Complex(ref mut complex) => match complex.content_mut() {
+                flat_ast::ComplexTypeContent::Seq(ref mut seq) => seq.elements_mut().iter_mut().for_each(repeat),
+                flat_ast::ComplexTypeContent::Choice(ref mut choice) => choice.elements_mut().iter_mut().for_each(repeat),
+                flat_ast::ComplexTypeContent::Empty => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+// an alias names a single file within the file importing it
+fn add_alias(imports: &mut HashMap<String, PathBuf>, import: &ast::Import, path: &Path) -> Result<(), ::failure::Error> {
+    match imports.get(&import.alias) {
+        Some(existing) if existing != path => Err(::failure::err_msg(format!("import alias {} is used for both {} and {}",
+            import.alias, existing.display(), path.display()))),
+        Some(_) => Ok(()),
+        None => {
+            imports.insert(import.alias.clone(), path.to_owned());
+            Ok(())
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+fn origin_name(path: &Path) -> String {
+    canonical(path).display().to_string()
+}
+
+// an imported file's types are generated once, in a namespace and file named after it
+pub fn import_origin(path: &Path) -> flat_ast::Origin {
+    use ::heck::{ToSnakeCase, ToUpperCamelCase};
+    let stem = path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    flat_ast::Origin {
+        path: origin_name(path),
+        namespace: format!("{}Types", stem.to_upper_camel_case()),
+        filename: format!("{}_types", stem.to_snake_case())
+    }
+}
+
+fn is_included(c: &ast::SequenceContent, version: Option<u32>, profiles: &[String]) -> bool {
+    match c {
+        ast::SequenceContent::Element(ref element) => element.availability().includes(version, profiles),
+        ast::SequenceContent::Padding(ref padding) => padding.availability().includes(version, profiles),
+        _ => true
+    }
+}
+
 fn flatten_seq_content(c: &ast::SequenceContent, ctx: &mut Context, id: u32) -> flat_ast::Element {
-    let (name, occurs, size_occurs, doc, content, inline) = match c {
+    let (name, occurs, size_occurs, doc, content, inline, condition) = match c {
         ast::SequenceContent::Element(ref element) => {
             return flatten_element(element, ctx, id);
         },
+        ast::SequenceContent::Padding(ref padding) => {
+            return flatten_padding(padding, ctx, id);
+        },
         ast::SequenceContent::Choice(ref choice) => {
             ctx.path.push("Choice".to_string());
             let choice = flatten_choice(choice, ctx);
@@ -312,32 +830,58 @@ fn flatten_seq_content(c: &ast::SequenceContent, ctx: &mut Context, id: u32) ->
             let name = ctx.get_anon_name();
             ctx.path.pop();
             let content = flat_ast::ComplexTypeContent::Choice(choice);
-            (name, occurs, size_occurs, doc, content, false)
+            (name, occurs, size_occurs, doc, content, false, None)
         },
         ast::SequenceContent::Seq(ref seq) => {
             ctx.path.push("Sequence".to_string());
-            let seq = flatten_seq(seq, ctx);
+            let condition = seq.condition().clone();
+            let mut seq = flatten_seq(seq, ctx);
             let inline = seq.inline();
-            let occurs = seq.occurs().clone();
-            let size_occurs = seq.size_occurs().clone();
+            let (occurs, size_occurs) = seq.take_occurs();
             let doc = seq.doc().clone();
             let name = ctx.get_anon_name();
             ctx.path.pop();
             let content = flat_ast::ComplexTypeContent::Seq(seq);
-            (name, occurs, size_occurs, doc, content, inline)
+            (name, occurs, size_occurs, doc, content, inline, condition)
         }
     };
 
     let complex = flat_ast::ComplexType::new(name.clone(), content, doc.clone(), true, inline);
     ctx.add_content(flat_ast::PacketContent::Complex(complex));
-    flat_ast::Element::new(name.clone(), name.clone(), id,
-        flat_ast::ElementInitValue::None, occurs, size_occurs, doc, true, true, None, None, None)
+    let mut element = flat_ast::Element::new(name.clone(), name.clone(), id,
+        flat_ast::ElementInitValue::None, occurs, size_occurs, doc, true, true, None, None, None);
+    if let Some(condition) = condition {
+        element.set_condition(condition);
+    }
+    element
+}
+
+fn flatten_padding(padding: &ast::Padding, ctx: &mut Context, id: u32) -> flat_ast::Element {
+    ctx.stop_bits();
+    let (name, occurs) = match padding.count() {
+        Some(count) => ("padding", Some(flat_ast::Occurs::Num(count.to_string()))),
+        None => ("reserved", None)
+    };
+    let mut element = flat_ast::Element::new(name.to_owned(), padding.type_().clone(), id,
+        flat_ast::ElementInitValue::None, occurs, None, None, false, false, None, None, None);
+    element.set_padding(padding.fill().clone());
+    element
 }
 
 fn flatten_element(elem: &ast::Element, ctx: &mut Context, id: u32) -> flat_ast::Element {
-    let init = elem.init().clone();
+    // fixed fields are initialized with their value since they have no setter, computed ones are filled in by pack()
+    let init = match (elem.fixed(), elem.compute()) {
+        (Some(ref fixed), _) => flat_ast::ElementInitValue::Default(fixed.clone()),
+        (None, Some(_)) => flat_ast::ElementInitValue::None,
+        (None, None) => elem.init().clone()
+    };
     let (name, type_, anonymous) = match elem.type_() {
-        ast::ElementType::Named{ ref name, ref type_ } => (name.clone(), type_.clone(), false),
+        ast::ElementType::Named{ ref name, .. } if elem.map().is_some() => {
+            let map = elem.map().as_ref().unwrap();
+            let container = if map.ordered { "std::map" } else { "std::unordered_map" };
+            (name.clone(), format!("{}<{}, {}>", container, map.key, map.value), false)
+        },
+        ast::ElementType::Named{ ref name, ref type_ } => (name.clone(), ctx.resolve_type(type_), false),
         ast::ElementType::Ref(ref name) => {
             if let Some(elem) = ctx.find_ref(name) {
                 (elem.name().clone(), elem.type_().clone(), elem.anonymous())
@@ -366,11 +910,403 @@ fn flatten_element(elem: &ast::Element, ctx: &mut Context, id: u32) -> flat_ast:
         ctx.stop_bits();
         None
     };
-    let mut element = flat_ast::Element::new(name, type_, id, init, elem.occurs().clone(),
+    // fixed length blobs are still exposed as a vector, read with a constant count
+    let (occurs, blob_length) = match (elem.occurs(), elem.occurs_ref()) {
+        (None, Some(_)) => (Some(flat_ast::Occurs::Unbounded), None),
+        (Some(flat_ast::Occurs::Num(ref length)), _) if elem.blob() => (Some(flat_ast::Occurs::Unbounded), Some(length.clone())),
+        (occurs, _) => (occurs.clone(), None)
+    };
+    let mut element = flat_ast::Element::new(name, type_, id, init, occurs,
         elem.size_occurs().clone(), elem.doc().clone(), anonymous, elem.reference(),
         elem.read_write().clone(), elem.bits(), bitset);
     if let Some(ref t) = elem.enum_type() {
         element.set_enum_type(t.clone());
     }
+    element.set_optional(elem.optional());
+    if let Some(ref occurs_ref) = elem.occurs_ref() {
+        element.set_occurs_ref(occurs_ref.clone());
+    }
+    element.set_inner_occurs(elem.inner_occurs().to_vec());
+    if let Some(ref condition) = elem.condition() {
+        element.set_condition(condition.clone());
+    }
+    if let Some(ref fixed) = elem.fixed() {
+        element.set_fixed(fixed.clone());
+    }
+    if let Some(ref compute) = elem.compute() {
+        element.set_compute(compute.clone());
+    }
+    element.set_blob(elem.blob());
+    if let Some(length) = blob_length {
+        element.set_blob_length(length);
+    }
+    if let Some(ref embeds) = elem.embeds() {
+        // a packet missing from the inputs is named like one without naming attributes
+        let (class_name, filename) = match ctx.packets.get(embeds) {
+            Some((class_name, filename, input)) => {
+                ctx.embedded.insert(canonical(input));
+                (class_name.clone(), filename.clone())
+            },
+            None => {
+                let (_, class_name, filename) = flat_ast::packet_names(embeds);
+                (class_name, filename)
+            }
+        };
+        element.set_embeds(flat_ast::EmbeddedPacket { class_name, filename });
+    }
+    if let Some(ref map) = elem.map() {
+        element.set_map(map.clone());
+    }
     element
 }
+
+#[cfg(test)]
+mod tests {
+    use ::flat_ast;
+    use super::{IncludeCache, Options, PacketNames};
+    use std::path::{Path, PathBuf};
+
+    fn flatten(xml: &str) -> Result<flat_ast::Packet, ::failure::Error> {
+        flatten_with(xml, &PacketNames::new())
+    }
+
+    fn flatten_with(xml: &str, packets: &PacketNames) -> Result<flat_ast::Packet, ::failure::Error> {
+        flatten_for(xml, None, &[], packets)
+    }
+
+    fn flatten_for(xml: &str, version: Option<u32>, profiles: &[String], packets: &PacketNames) -> Result<flat_ast::Packet, ::failure::Error> {
+        let packet = ::schema::Reader::load_packet(::std::io::Cursor::new(xml.to_owned()))?;
+        let cache = IncludeCache::default();
+        let options = Options { version, profiles, shared: false, include_dirs: &[], cache: &cache, packets };
+        Ok(super::flatten(Path::new("test.xml"), &packet, &options)?.0)
+    }
+
+    // a fresh directory holding the given files
+    fn files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = ::testing::temp_dir(&format!("flatten_{}", test));
+        for (name, content) in files {
+            let path = dir.join(name);
+            ::std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            ::std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn flatten_file(path: &Path, include_dirs: &[PathBuf]) -> Result<(flat_ast::Packet, Vec<PathBuf>), ::failure::Error> {
+        let packet = ::schema::Reader::load_packet(::std::fs::File::open(path)?)?;
+        let (cache, packets) = (IncludeCache::default(), PacketNames::new());
+        let options = Options { version: None, profiles: &[], shared: false, include_dirs, cache: &cache, packets: &packets };
+        super::flatten(path, &packet, &options)
+    }
+
+    fn type_names(packet: &flat_ast::Packet) -> Vec<String> {
+        packet.contents().iter().filter_map(flat_ast::PacketContent::type_from_name).collect()
+    }
+
+    fn element<'a>(packet: &'a flat_ast::Packet, name: &str) -> &'a flat_ast::Element {
+        packet.contents().iter().filter_map(|content| match content {
+            flat_ast::PacketContent::Element(ref elem) => Some(&**elem),
+            _ => None
+        }).find(|elem| elem.name() == name).unwrap()
+    }
+
+    fn element_names(packet: &flat_ast::Packet) -> Vec<String> {
+        packet.contents().iter().filter_map(|content| match content {
+            flat_ast::PacketContent::Element(ref elem) => Some(elem.name().clone()),
+            _ => None
+        }).collect()
+    }
+
+    fn flags(packet: &flat_ast::Packet) -> Vec<(String, i64)> {
+        let simple = packet.contents().iter().find_map(|content| match content {
+            flat_ast::PacketContent::Simple(ref simple) => Some(simple),
+            _ => None
+        }).unwrap();
+        let flat_ast::SimpleTypeContent::Restriction(ref restriction) = simple.contents()[0];
+        restriction.contents().iter().filter_map(|content| match content {
+            flat_ast::RestrictionContent::Enumeration(ref e) => Some((e.value().clone(), e.id())),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn flag_bits() {
+        let packet = flatten(r#"<packet ePacketType="PAKCS_PACKET">
+            <simpleType name="Status">
+                <restriction base="uint8_t" flags="true">
+                    <enumeration value="A"/>
+                    <enumeration value="B"/>
+                    <enumeration value="C" bit="4"/>
+                    <enumeration value="D"/>
+                    <enumeration value="E" id="96"/>
+                </restriction>
+            </simpleType>
+        </packet>"#).unwrap();
+        assert_eq!(flags(&packet), vec![("A".to_owned(), 1), ("B".to_owned(), 2), ("C".to_owned(), 16), ("D".to_owned(), 32), ("E".to_owned(), 96)]);
+    }
+
+    #[test]
+    fn flag_outside_base() {
+        let error = flatten(r#"<packet ePacketType="PAKCS_PACKET">
+            <simpleType name="Status">
+                <restriction base="uint8_t" flags="true">
+                    <enumeration value="A" bit="8"/>
+                </restriction>
+            </simpleType>
+        </packet>"#).unwrap_err();
+        assert_eq!(error.to_string(), "type status: flag A is bit 8, outside of its 8 bits base uint8_t");
+    }
+
+    #[test]
+    fn embedded_packets() {
+        let mut packets = PacketNames::new();
+        packets.insert("PAKCS_INNER".to_owned(), ("InnerRequest".to_owned(), "inner_request".to_owned(), "inner.xml".into()));
+        let packet = flatten_with(r#"<packet ePacketType="PAKCS_PACKET">
+            <bytes name="known" occursSize="uint16_t" embeds="PAKCS_INNER"/>
+            <bytes name="unknown" occursSize="uint16_t" embeds="PAKWC_OTHER"/>
+        </packet>"#, &packets).unwrap();
+        let embeds = packet.contents().iter().filter_map(|content| match content {
+            flat_ast::PacketContent::Element(ref elem) => elem.embeds().clone(),
+            _ => None
+        }).map(|embeds| (embeds.class_name, embeds.filename)).collect::<Vec<_>>();
+        assert_eq!(embeds, vec![
+            ("InnerRequest".to_owned(), "inner_request".to_owned()),
+            ("SrvOther".to_owned(), "srv_other".to_owned())
+        ]);
+    }
+
+    #[test]
+    fn paddings() {
+        let packet = flatten(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="hp" type="uint16_t"/>
+            <padding bytes="3" fill="0xFF"/>
+            <reserved type="uint32_t"/>
+        </packet>"#).unwrap();
+        assert_eq!(element_names(&packet), vec!["hp", "padding", "reserved"]);
+        let padding = element(&packet, "padding");
+        assert_eq!(*padding.type_(), "uint8_t");
+        assert_eq!(*padding.occurs(), Some(flat_ast::Occurs::Num("3".to_owned())));
+        assert_eq!(*padding.padding(), Some("0xFF".to_owned()));
+        let reserved = element(&packet, "reserved");
+        assert_eq!(*reserved.type_(), "uint32_t");
+        assert_eq!(*reserved.occurs(), None);
+        assert_eq!(*reserved.padding(), Some("0".to_owned()));
+    }
+
+    #[test]
+    fn versions() {
+        let xml = r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="old" type="uint8_t" until="3"/>
+            <element name="both" type="uint8_t"/>
+            <element name="new" type="uint16_t" since="4"/>
+        </packet>"#;
+        let names = |version| element_names(&flatten_for(xml, version, &[], &PacketNames::new()).unwrap());
+        assert_eq!(names(Some(2)), vec!["old", "both"]);
+        assert_eq!(names(Some(4)), vec!["both", "new"]);
+        assert_eq!(names(None), vec!["both", "new"]);
+    }
+
+    #[test]
+    fn profiles() {
+        let xml = r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="server" type="uint8_t" profile="server"/>
+            <element name="both" type="uint8_t"/>
+            <element name="client" type="uint8_t" profile="client"/>
+        </packet>"#;
+        let names = |profiles: &[String]| element_names(&flatten_for(xml, None, profiles, &PacketNames::new()).unwrap());
+        assert_eq!(names(&["client".to_owned()]), vec!["both", "client"]);
+        assert_eq!(names(&["server".to_owned(), "client".to_owned()]), vec!["server", "both", "client"]);
+        assert_eq!(names(&[]), vec!["server", "both", "client"]);
+    }
+
+    #[test]
+    fn packet_names() {
+        let names = |xml: &str| super::packet_names(&::schema::Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).unwrap());
+        let pair = |class_name: &str, filename: &str| (class_name.to_owned(), filename.to_owned());
+        assert_eq!(names(r#"<packet ePacketType="PAKCS_LOGIN_REQ" direction="client"/>"#), pair("CliLoginReq", "cli_login_req"));
+        assert_eq!(names(r#"<packet ePacketType="PAKCS_LOGIN_REQ" direction="inter"/>"#), pair("IscLoginReq", "isc_login_req"));
+        assert_eq!(names(r#"<packet ePacketType="PAKCS_LOGIN_REQ" className="LoginRequest"/>"#), pair("LoginRequest", "login_request"));
+        assert_eq!(names(r#"<packet ePacketType="PAKCS_LOGIN_REQ" direction="client" fileName="login"/>"#), pair("CliLoginReq", "login"));
+    }
+
+    #[test]
+    fn fallback_packet_names() {
+        use ::flat_ast::Direction;
+        let names = |type_: &str, direction: Direction, class_name: &str, filename: &str| {
+            assert_eq!(flat_ast::packet_names(type_), (direction, class_name.to_owned(), filename.to_owned()));
+        };
+        names("PAKCS_LOGIN_REQ", Direction::Client, "CliLoginReq", "cli_login_req");
+        names("PAKSC_LOGIN_REPLY", Direction::Server, "SrvLoginReply", "srv_login_reply");
+        names("PAKWC_CHAR_LIST", Direction::Server, "SrvCharList", "srv_char_list");
+        names("ISC_SHUTDOWN", Direction::Inter, "IscShutdown", "isc_shutdown");
+        names("pakcs_chat", Direction::Client, "CliChat", "cli_chat");
+    }
+
+    const COMMON: &str = r#"<packet ePacketType="COMMON">
+        <simpleType name="Hp"><restriction base="uint16_t"/></simpleType>
+        <complexType name="Pos"><sequence><element name="x" type="float"/><element name="hp" type="Hp"/></sequence></complexType>
+    </packet>"#;
+
+    #[test]
+    fn imports() {
+        let dir = files("imports", &[
+            ("a.xml", r#"<packet ePacketType="PAKCS_A">
+                <import path="lib/common.xml" as="c"/>
+                <element name="where" type="c::Pos"/>
+            </packet>"#),
+            ("lib/common.xml", COMMON)
+        ]);
+        let (packet, dependencies) = flatten_file(&dir.join("a.xml"), &[]).unwrap();
+        assert_eq!(dependencies, vec![dir.join("lib/common.xml")]);
+        let mut names = type_names(&packet);
+        names.sort();
+        assert_eq!(names, vec!["hp", "pos"]);
+        let origin = super::import_origin(&dir.join("lib/common.xml"));
+        assert_eq!((origin.namespace.as_str(), origin.filename.as_str()), ("CommonTypes", "common_types"));
+        assert!(packet.contents().iter().filter(|content| flat_ast::PacketContent::is_type(content)).all(|content| flat_ast::PacketContent::origin(content) == Some(&origin)));
+        assert_eq!(element(&packet, "where").type_(), "pos");
+
+        let (packet, dependencies) = super::flatten_import(&dir.join("lib/common.xml"), &Options {
+            version: None, profiles: &[], shared: false, include_dirs: &[], cache: &IncludeCache::default(), packets: &PacketNames::new()
+        }).unwrap();
+        assert_eq!(dependencies, vec![dir.join("lib/common.xml")]);
+        assert_eq!(type_names(&packet), vec!["hp", "pos"]);
+        assert!(packet.contents().iter().all(|content| flat_ast::PacketContent::origin(content).is_none()));
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_errors() {
+        let dir = files("import_errors", &[
+            ("unqualified.xml", r#"<packet ePacketType="PAKCS_A">
+                <import path="common.xml" as="c"/>
+                <element name="hp" type="Hp"/>
+            </packet>"#),
+            ("ambiguous.xml", r#"<packet ePacketType="PAKCS_A">
+                <import path="common.xml" as="c"/>
+                <import path="other.xml" as="o"/>
+                <element name="hp" type="Hp"/>
+            </packet>"#),
+            ("missing.xml", r#"<packet ePacketType="PAKCS_A">
+                <import path="common.xml" as="c"/>
+                <element name="mp" type="c::Mp"/>
+            </packet>"#),
+            ("alias.xml", r#"<packet ePacketType="PAKCS_A">
+                <import path="common.xml" as="c"/>
+                <import path="other.xml" as="c"/>
+            </packet>"#),
+            ("common.xml", COMMON),
+            ("other.xml", r#"<packet ePacketType="OTHER">
+                <simpleType name="Hp"><restriction base="uint32_t"/></simpleType>
+            </packet>"#)
+        ]);
+        let error = |name: &str| flatten_file(&dir.join(name), &[]).unwrap_err().to_string();
+        assert_eq!(error("unqualified.xml"), "type Hp is not defined in the packet, use c::Hp");
+        assert_eq!(error("ambiguous.xml"), "type Hp is ambiguous between the imports c, o");
+        assert_eq!(error("missing.xml"), format!("type Mp not found in import c ({})", dir.join("common.xml").display()));
+        assert_eq!(error("alias.xml"), format!("import alias c is used for both {} and {}", dir.join("common.xml").display(), dir.join("other.xml").display()));
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn import_scopes() {
+        let dir = files("import_scopes", &[
+            ("a.xml", r#"<packet ePacketType="PAKCS_A">
+                <import path="lib/left.xml" as="l"/>
+                <import path="lib/right.xml" as="r"/>
+                <element name="left" type="l::Left"/>
+                <element name="right" type="r::Right"/>
+            </packet>"#),
+            ("leaked.xml", r#"<packet ePacketType="PAKCS_A">
+                <import path="lib/left.xml" as="l"/>
+                <element name="left" type="l::Left"/>
+                <element name="one" type="x::One"/>
+            </packet>"#),
+            ("included.xml", r#"<packet ePacketType="PAKCS_A">
+                <includeXml path="inc.xml"/>
+                <element name="one" type="x::One"/>
+            </packet>"#),
+            ("inc.xml", r#"<packet ePacketType="INC">
+                <import path="lib/one.xml" as="x"/>
+                <complexType name="Inc"><sequence><element name="one" type="x::One"/></sequence></complexType>
+            </packet>"#),
+            // siblings and nested files use the same alias for different files
+            ("lib/left.xml", r#"<packet ePacketType="LEFT">
+                <import path="one.xml" as="x"/>
+                <complexType name="Left"><sequence><element name="one" type="x::One"/></sequence></complexType>
+            </packet>"#),
+            ("lib/right.xml", r#"<packet ePacketType="RIGHT">
+                <import path="two.xml" as="x"/>
+                <complexType name="Right"><sequence><element name="two" type="x::Two"/></sequence></complexType>
+            </packet>"#),
+            ("lib/one.xml", r#"<packet ePacketType="ONE">
+                <import path="two.xml" as="x"/>
+                <complexType name="One"><sequence><element name="two" type="x::Two"/></sequence></complexType>
+            </packet>"#),
+            ("lib/two.xml", r#"<packet ePacketType="TWO">
+                <simpleType name="Two"><restriction base="uint8_t"/></simpleType>
+            </packet>"#)
+        ]);
+        let (packet, dependencies) = flatten_file(&dir.join("a.xml"), &[]).unwrap();
+        let mut names = type_names(&packet);
+        names.sort();
+        assert_eq!(names, vec!["left", "one", "right", "two"]);
+        assert_eq!(dependencies, ["left.xml", "one.xml", "right.xml", "two.xml"].iter().map(|name| dir.join("lib").join(name)).collect::<Vec<_>>());
+        let error = |name: &str| flatten_file(&dir.join(name), &[]).unwrap_err().to_string();
+        assert_eq!(error("leaked.xml"), "type x::One uses the alias x which is not imported by the file using it");
+        assert_eq!(error("included.xml"), "type x::One uses the alias x which is not imported by the file using it");
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn includes() {
+        let dir = files("includes", &[
+            ("a.xml", r#"<packet ePacketType="PAKCS_A">
+                <includeXml path="lib/outer.xml"/>
+                <includeXml path="lib/sub/inner.xml"/>
+                <includeXml path="found.xml"/>
+                <element name="v" type="Inner"/>
+            </packet>"#),
+            ("lib/outer.xml", r#"<packet ePacketType="OUTER">
+                <includeXml path="sub/inner.xml"/>
+                <simpleType name="Outer"><restriction base="uint8_t"/></simpleType>
+            </packet>"#),
+            ("lib/sub/inner.xml", r#"<packet ePacketType="INNER">
+                <simpleType name="Inner"><restriction base="uint8_t"/></simpleType>
+            </packet>"#),
+            ("include/found.xml", r#"<packet ePacketType="FOUND">
+                <simpleType name="Found"><restriction base="uint8_t"/></simpleType>
+            </packet>"#)
+        ]);
+        let (packet, dependencies) = flatten_file(&dir.join("a.xml"), &[dir.join("include")]).unwrap();
+        // the nested include is found next to the file including it and only flattened once
+        assert_eq!(type_names(&packet), vec!["inner", "outer", "found"]);
+        assert_eq!(dependencies, vec![dir.join("include/found.xml"), dir.join("lib/outer.xml"), dir.join("lib/sub/inner.xml")]);
+        assert_eq!(flatten_file(&dir.join("a.xml"), &[]).unwrap_err().to_string(),
+            format!("found.xml not found in {} or the include directories", dir.display()));
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = files("include_cycle", &[
+            ("a.xml", r#"<packet ePacketType="PAKCS_A"><includeXml path="b.xml"/></packet>"#),
+            ("b.xml", r#"<packet ePacketType="B"><includeXml path="c.xml"/></packet>"#),
+            ("c.xml", r#"<packet ePacketType="C"><includeXml path="b.xml"/></packet>"#)
+        ]);
+        assert_eq!(flatten_file(&dir.join("a.xml"), &[]).unwrap_err().to_string(), format!("include cycle: {} -> {} -> {} -> {}",
+            dir.join("a.xml").display(), dir.join("b.xml").display(), dir.join("c.xml").display(), dir.join("b.xml").display()));
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_cache() {
+        let dir = files("include_cache", &[("common.xml", COMMON)]);
+        let cache = IncludeCache::default();
+        let first = cache.load(&dir.join("common.xml")).unwrap();
+        let second = cache.load(&dir.join(".").join("common.xml")).unwrap();
+        assert!(::std::sync::Arc::ptr_eq(&first, &second));
+        assert!(cache.load(&dir.join("missing.xml")).is_err());
+        ::std::fs::remove_dir_all(dir).unwrap();
+    }
+}
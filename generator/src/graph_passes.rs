@@ -183,9 +183,99 @@ fn visit(node: NodeId, graph: &mut Graph, depth: u32) {
     }
 }
 
-pub fn run(mut packet: Packet) -> Result<Packet, ::failure::Error> {
+pub fn run(packet: Packet) -> Result<Packet, ::failure::Error> {
+    run_(packet, false)
+}
+
+// the types of an imported file are generated whether or not one of its elements uses them
+pub fn run_types(packet: Packet) -> Result<Packet, ::failure::Error> {
+    run_(packet, true)
+}
+
+fn run_(mut packet: Packet, keep_types: bool) -> Result<Packet, ::failure::Error> {
     use self::NodeType::*;
 
+    link_occurs_refs(packet.contents_mut().iter_mut().filter_map(|content| match content {
+        PacketContent::Element(ref mut e) => Some(&mut **e),
+        _ => None
+    }).collect())?;
+    link_computes(packet.contents_mut().iter_mut().filter_map(|content| match content {
+        PacketContent::Element(ref mut e) => Some(&mut **e),
+        _ => None
+    }).collect())?;
+    check_conditions(&packet.contents().iter().filter_map(|content| match content {
+        PacketContent::Element(ref e) => Some(&**e),
+        _ => None
+    }).collect::<Vec<_>>())?;
+    for content in packet.contents() {
+        match content {
+            PacketContent::Element(ref e) => {
+                check_fixed(e)?;
+                check_padding(e)?;
+                check_blob(e)?;
+                check_map(e)?;
+            },
+            PacketContent::Complex(ref c) => match c.content() {
+                ComplexTypeContent::Seq(ref s) => for e in s.elements() {
+                    check_fixed(e)?;
+                    check_padding(e)?;
+                    check_blob(e)?;
+                    check_map(e)?;
+                },
+                ComplexTypeContent::Choice(ref choice) => {
+                    if let Some(e) = choice.elements().iter().find(|e| e.fixed().is_some()) {
+                        return Err(::failure::err_msg(format!("fixed element {} cannot be part of a choice", e.name())));
+                    }
+                    if choice.elements().iter().any(|e| e.padding().is_some()) {
+                        return Err(::failure::err_msg("padding and reserved fields cannot be part of a choice"));
+                    }
+                    if let Some(e) = choice.elements().iter().find(|e| e.blob_length().is_some() || e.embeds().is_some()) {
+                        return Err(::failure::err_msg(format!("bytes element {} cannot be part of a choice", e.name())));
+                    }
+                    if let Some(e) = choice.elements().iter().find(|e| e.map().is_some()) {
+                        return Err(::failure::err_msg(format!("map element {} cannot be part of a choice", e.name())));
+                    }
+                },
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    for content in packet.contents_mut() {
+        if let PacketContent::Complex(ref mut c) = content {
+            match c.content_mut() {
+                ComplexTypeContent::Seq(ref mut s) => {
+                    link_occurs_refs(s.elements_mut().iter_mut().collect())?;
+                    link_computes(s.elements_mut().iter_mut().collect())?;
+                    check_conditions(&s.elements().iter().collect::<Vec<_>>())?;
+                },
+                ComplexTypeContent::Choice(ref choice) => {
+                    if let Some(e) = choice.elements().iter().find(|e| e.occurs_ref().is_some()) {
+                        return Err(::failure::err_msg(format!("element {} with occursRef cannot be part of a choice", e.name())));
+                    }
+                    if let Some(e) = choice.elements().iter().find(|e| e.condition().is_some()) {
+                        return Err(::failure::err_msg(format!("conditional element {} cannot be part of a choice", e.name())));
+                    }
+                    if let Some(e) = choice.elements().iter().find(|e| e.compute().is_some()) {
+                        return Err(::failure::err_msg(format!("computed element {} cannot be part of a choice", e.name())));
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+    check_compute_fields(&packet.contents().iter().filter_map(|content| match content {
+        PacketContent::Element(ref e) => Some(&**e),
+        _ => None
+    }).collect::<Vec<_>>(), &packet)?;
+    for content in packet.contents() {
+        if let PacketContent::Complex(ref c) = content {
+            if let ComplexTypeContent::Seq(ref s) = c.content() {
+                check_compute_fields(&s.elements().iter().collect::<Vec<_>>(), &packet)?;
+            }
+        }
+    }
+
     let mut graph = Graph::new();
 
     let mut enums = ::std::collections::HashSet::<String>::new();
@@ -262,15 +352,30 @@ pub fn run(mut packet: Packet) -> Result<Packet, ::failure::Error> {
                 trace!("adding start node {}", e.type_());
                 graph.add_start_node(&e.type_().to_owned().to_lower_camel_case());
                 match e.occurs() {
+                    _ if e.padding().is_some() => {},
                     Some(self::Occurs::Unbounded) => vector = true,
                     Some(self::Occurs::Num(_)) => array = true,
                     _ => {}
                 };
+                for inner in e.inner_occurs() {
+                    match inner.occurs {
+                        self::Occurs::Unbounded => vector = true,
+                        self::Occurs::Num(_) => array = true
+                    };
+                }
             },
             _ => {}
         }
     }
 
+    if keep_types {
+        for content in packet.contents() {
+            if let Some(name) = PacketContent::type_from_name(content) {
+                graph.add_start_node(&name);
+            }
+        }
+    }
+
     graph.run();
 
     // depth-first, post-traversal dependencies check
@@ -405,6 +510,90 @@ pub fn run(mut packet: Packet) -> Result<Packet, ::failure::Error> {
         }
     }
 
+    for content in packet.contents() {
+        match content {
+            PacketContent::Element(ref e) => check_inner_occurs(e)?,
+            PacketContent::Complex(ref c) => match c.content() {
+                ComplexTypeContent::Seq(ref s) => for e in s.elements() {
+                    check_inner_occurs(e)?;
+                },
+                ComplexTypeContent::Choice(ref choice) => {
+                    if let Some(e) = choice.elements().iter().find(|e| !e.inner_occurs().is_empty()) {
+                        return Err(::failure::err_msg(format!("multi-dimensional element {} cannot be part of a choice", e.name())));
+                    }
+                },
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    let mut optional = check_presence(packet.presence(), packet.contents().iter().filter_map(|content| match content {
+        PacketContent::Element(ref e) => Some(&**e),
+        _ => None
+    }))?;
+    for content in packet.contents() {
+        if let PacketContent::Complex(ref c) = content {
+            match c.content() {
+                ComplexTypeContent::Seq(ref s) => optional |= check_presence(s.presence(), s.elements().iter())?,
+                ComplexTypeContent::Choice(ref choice) => {
+                    if let Some(e) = choice.elements().iter().find(|e| e.optional()) {
+                        return Err(::failure::err_msg(format!("optional element {} cannot be part of a choice", e.name())));
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    let mut scaled = false;
+    for content in packet.contents() {
+        if let PacketContent::Simple(ref simple) = content {
+            for content in simple.contents() {
+                let SimpleTypeContent::Restriction(ref r) = content;
+                scaled |= check_scale(r, simple.name())?;
+            }
+        }
+    }
+    if scaled {
+        for include in &["algorithm", "cmath", "limits"] {
+            packet.add_content(self::PacketContent::Include(include.to_string(), true));
+        }
+    }
+
+    // blobs embedding another packet need its class to parse themselves
+    let mut embeds = Vec::new();
+    for content in packet.contents() {
+        let elements = match content {
+            PacketContent::Element(ref e) => vec![&**e],
+            PacketContent::Complex(ref c) => match c.content() {
+                ComplexTypeContent::Seq(ref s) => s.elements().iter().collect(),
+                _ => Vec::new()
+            },
+            _ => Vec::new()
+        };
+        for e in elements {
+            if let Some(ref embedded) = e.embeds() {
+                if !embeds.contains(&embedded.filename) {
+                    embeds.push(embedded.filename.clone());
+                }
+            }
+        }
+    }
+    for filename in embeds {
+        packet.add_content(self::PacketContent::Include(filename + ".h", false));
+    }
+
+    // computed fields gather the bytes they cover into a vector
+    vector |= packet.contents().iter().any(|content| match content {
+        PacketContent::Element(ref e) => e.compute().is_some(),
+        PacketContent::Complex(ref c) => match c.content() {
+            ComplexTypeContent::Seq(ref s) => s.elements().iter().any(|e| e.compute().is_some()),
+            _ => false
+        },
+        _ => false
+    });
+
     if vector {
         packet.add_content(self::PacketContent::Include("vector".to_owned(), true));
     }
@@ -413,5 +602,646 @@ pub fn run(mut packet: Packet) -> Result<Packet, ::failure::Error> {
         packet.add_content(self::PacketContent::Include("array".to_owned(), true));
     }
 
+    // maps look their values up through an optional
+    let mut maps = Vec::new();
+    for content in packet.contents() {
+        let elements = match content {
+            PacketContent::Element(ref e) => vec![&**e],
+            PacketContent::Complex(ref c) => match c.content() {
+                ComplexTypeContent::Seq(ref s) => s.elements().iter().collect(),
+                _ => Vec::new()
+            },
+            _ => Vec::new()
+        };
+        for e in elements {
+            if let Some(ref map) = e.map() {
+                let include = if map.ordered { "map" } else { "unordered_map" };
+                if !maps.contains(&include) {
+                    maps.push(include);
+                }
+                optional = true;
+            }
+        }
+    }
+    for include in maps {
+        packet.add_content(self::PacketContent::Include(include.to_string(), true));
+    }
+
+    if optional {
+        packet.add_content(self::PacketContent::Include("optional".to_owned(), true));
+    }
+
+    // types defined elsewhere are generated in the shared types or in their imported file's output
+    let mut origins = packet.contents().iter().filter_map(PacketContent::origin)
+        .map(|origin| origin.filename.clone() + ".h").collect::<Vec<_>>();
+    origins.sort();
+    origins.dedup();
+    for include in origins {
+        packet.add_content(self::PacketContent::Include(include, false));
+    }
+
     Ok(packet)
 }
+
+// gathers the shared types of a packet into the common types, which every packet refers to
+pub fn merge_shared(types: &mut Packet, packet: &Packet) -> Result<(), ::failure::Error> {
+    let shared = packet.contents().iter().filter(|content| PacketContent::origin(content).is_some()).collect::<Vec<_>>();
+    if shared.is_empty() {
+        return Ok(());
+    }
+    for content in shared {
+        let name = PacketContent::type_from_name(content).unwrap();
+        let origin = PacketContent::origin(content).unwrap();
+        let existing = types.contents().iter().find(|other| PacketContent::type_from_name(other).as_ref() == Some(&name))
+            .map(|other| PacketContent::origin(other).cloned());
+        match existing {
+            // the types of imported files are only brought in for the shared types using them
+            Some(Some(ref other)) if other.namespace != ::flat_ast::SHARED_TYPES_NAMESPACE
+                || origin.namespace != ::flat_ast::SHARED_TYPES_NAMESPACE => {},
+            Some(other) if other.as_ref() != Some(origin) => {
+                return Err(::failure::err_msg(format!("shared type {} is defined in both {} and {}",
+                    name, other.map(|other| other.path).unwrap_or_default(), origin.path)));
+            },
+            Some(_) => {},
+            None => types.add_content(content.clone())
+        }
+    }
+    let shared_header = ::flat_ast::SHARED_TYPES_FILENAME.to_owned() + ".h";
+    for content in packet.contents() {
+        if let PacketContent::Include(ref path, system) = content {
+            let known = types.contents().iter().any(|other| match other {
+                PacketContent::Include(ref other, _) => other == path,
+                _ => false
+            });
+            if !known && *path != shared_header {
+                types.add_content(PacketContent::Include(path.clone(), *system));
+            }
+        }
+    }
+    Ok(())
+}
+
+// checks that optional elements have a presence mask large enough to hold them
+fn check_presence<'a>(presence: &Option<String>, elements: impl Iterator<Item = &'a Element>) -> Result<bool, ::failure::Error> {
+    let mut count = 0;
+    for elem in elements.filter(|e| e.optional()) {
+        if presence.is_none() {
+            return Err(::failure::err_msg(format!("optional element {} requires a presence mask", elem.name())));
+        }
+        if elem.bits().is_some() {
+            return Err(::failure::err_msg(format!("optional element {} cannot be a bitfield", elem.name())));
+        }
+        count += 1;
+    }
+    if let Some(ref presence) = presence {
+        let size = match presence.as_str() {
+            "uint8_t" => 8,
+            "uint16_t" => 16,
+            "uint32_t" => 32,
+            "uint64_t" => 64,
+            _ => return Err(::failure::err_msg(format!("presence mask {} is not an unsigned integer type", presence)))
+        };
+        if count > size {
+            return Err(::failure::err_msg(format!("presence mask {} cannot hold {} optional elements", presence, count)));
+        }
+    }
+    Ok(count != 0)
+}
+
+// marks the elements named by occursRef as the count of the vector referencing them
+fn link_occurs_refs(mut elements: Vec<&mut Element>) -> Result<(), ::failure::Error> {
+    for i in 0..elements.len() {
+        let (name, occurs_ref) = match elements[i].occurs_ref() {
+            Some(ref occurs_ref) => (elements[i].name().clone(), occurs_ref.clone()),
+            None => continue
+        };
+        if elements[i].occurs() != &Some(Occurs::Unbounded) {
+            return Err(::failure::err_msg(format!("element {} with occursRef must be unbounded", name)));
+        }
+        if elements[i].size_occurs().is_some() || elements[i].optional() {
+            return Err(::failure::err_msg(format!("element {} with occursRef cannot be optional or have an occursSize", name)));
+        }
+        let count = match elements[..i].iter_mut().find(|e| *e.name() == occurs_ref) {
+            Some(count) => count,
+            None => return Err(::failure::err_msg(format!("occursRef {} of element {} must name an earlier element", occurs_ref, name)))
+        };
+        if count.occurs().is_some() || count.bits().is_some() || count.optional() || count.count_for().is_some() {
+            return Err(::failure::err_msg(format!("element {} cannot be used as the count of {}", occurs_ref, name)));
+        }
+        count.set_count_for(name);
+    }
+    Ok(())
+}
+
+// the field a condition tests has to be read before the element it guards
+fn check_conditions(elements: &[&Element]) -> Result<(), ::failure::Error> {
+    for (i, elem) in elements.iter().enumerate() {
+        let condition = match elem.condition() {
+            Some(ref condition) => condition,
+            None => continue
+        };
+        if elem.bits().is_some() {
+            return Err(::failure::err_msg(format!("conditional element {} cannot be a bitfield", elem.name())));
+        }
+        let field = match elements[..i].iter().find(|e| *e.name() == condition.field) {
+            Some(field) => field,
+            None => return Err(::failure::err_msg(format!("condition field {} of element {} must name an earlier element", condition.field, elem.name())))
+        };
+        if field.occurs().is_some() || field.map().is_some() || field.optional() || field.anonymous() {
+            return Err(::failure::err_msg(format!("element {} cannot be used in the condition of {}", condition.field, elem.name())));
+        }
+    }
+    Ok(())
+}
+
+// fixed values are written as literals, so only plain integer fields can hold them
+fn check_fixed(elem: &Element) -> Result<(), ::failure::Error> {
+    if elem.fixed().is_none() {
+        return Ok(());
+    }
+    if !is_integer_type(elem.type_()) {
+        return Err(::failure::err_msg(format!("fixed element {} must have an integer type, not {}", elem.name(), elem.type_())));
+    }
+    if elem.occurs().is_some() || elem.bits().is_some() || elem.optional() || elem.condition().is_some() || elem.count_for().is_some() {
+        return Err(::failure::err_msg(format!("fixed element {} cannot be a container, bitfield, optional, conditional or count", elem.name())));
+    }
+    let fixed = elem.fixed().as_ref().unwrap();
+    match parse_integer(fixed) {
+        Some(value) if integer_range(elem.type_()).contains(&value) => Ok(()),
+        Some(_) => Err(::failure::err_msg(format!("fixed value {} of element {} does not fit in {}", fixed, elem.name(), elem.type_()))),
+        None => Err(::failure::err_msg(format!("fixed value {} of element {} is not an integer", fixed, elem.name())))
+    }
+}
+
+// a decimal or hexadecimal integer literal, as it can be pasted in the generated code
+fn parse_integer(literal: &str) -> Option<i128> {
+    let (negative, digits) = match literal.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, literal)
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) if hex.chars().all(|c| c.is_ascii_hexdigit()) => i128::from_str_radix(hex, 16).ok()?,
+        Some(_) => return None,
+        None if digits.starts_with(|c: char| c.is_ascii_digit()) => digits.parse::<i128>().ok()?,
+        None => return None
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn integer_range(type_: &str) -> ::std::ops::RangeInclusive<i128> {
+    match type_ {
+        "int8_t" => i8::MIN as i128..=i8::MAX as i128,
+        "uint8_t" => 0..=u8::MAX as i128,
+        "int16_t" => i16::MIN as i128..=i16::MAX as i128,
+        "uint16_t" => 0..=u16::MAX as i128,
+        "int32_t" => i32::MIN as i128..=i32::MAX as i128,
+        "uint32_t" => 0..=u32::MAX as i128,
+        "int64_t" => i64::MIN as i128..=i64::MAX as i128,
+        _ => 0..=u64::MAX as i128
+    }
+}
+
+// resolves the range each computed element covers, defaulting to every element before it
+fn link_computes(mut elements: Vec<&mut Element>) -> Result<(), ::failure::Error> {
+    for i in 0..elements.len() {
+        let compute = match elements[i].compute() {
+            Some(ref compute) => compute.clone(),
+            None => continue
+        };
+        let name = elements[i].name().clone();
+        if !is_integer_type(elements[i].type_()) {
+            return Err(::failure::err_msg(format!("computed element {} must have an integer type", name)));
+        }
+        if elements[i].occurs().is_some() || elements[i].bits().is_some() || elements[i].optional()
+            || elements[i].condition().is_some() || elements[i].fixed().is_some() || elements[i].count_for().is_some() {
+            return Err(::failure::err_msg(format!("computed element {} cannot be a container, bitfield, optional, conditional, fixed or count", name)));
+        }
+        let position = |field: &str| elements.iter().position(|e| e.name() == field)
+            .ok_or_else(|| ::failure::err_msg(format!("range {} of computed element {} must name an element", field, name)));
+        let (start, end) = match (compute.start, compute.end) {
+            (Some(start), Some(end)) => (position(&start)?, position(&end)?),
+            _ if compute.kind == ComputeKind::Length => {
+                return Err(::failure::err_msg(format!("computed length {} needs a range", name)));
+            },
+            _ if i == 0 => return Err(::failure::err_msg(format!("computed element {} has nothing to cover", name))),
+            _ => (0, i - 1)
+        };
+        if start > end || (start..=end).contains(&i) {
+            return Err(::failure::err_msg(format!("range of computed element {} is empty or contains itself", name)));
+        }
+        // bitfields are written together as one bitset, so the range has to cover all of it
+        let bitset = |index: usize| elements.get(index).and_then(|e| e.bitset().as_ref()).map(|bitset| bitset.name.clone());
+        let splits_start = elements[start].bitset().as_ref().is_some_and(|bitset| bitset.start != 0);
+        let splits_end = bitset(end).is_some() && bitset(end) == bitset(end + 1);
+        if splits_start || splits_end {
+            return Err(::failure::err_msg(format!("range of computed element {} cannot cover only part of a bitfield set", name)));
+        }
+        let range = elements[start..=end].iter().map(|e| e.name().clone()).collect();
+        elements[i].set_compute_range(range);
+    }
+    // a computed field can cover another one (e.g. a crc over a length) but not one that covers more computed fields
+    for elem in elements.iter().filter(|e| e.compute().is_some()) {
+        for field in elements.iter().filter(|e| e.compute().is_some() && elem.compute_range().contains(e.name())) {
+            if field.compute_range().iter().any(|name| elements.iter().any(|e| e.name() == name && e.compute().is_some())) {
+                return Err(::failure::err_msg(format!("computed element {} cannot cover {} which covers other computed elements", elem.name(), field.name())));
+            }
+        }
+    }
+    Ok(())
+}
+
+// a computed field is checked on read by writing the covered fields back, so they must come out as they were read:
+// padding is skipped whatever its content and an unordered map loses the order of its entries
+fn check_compute_fields(elements: &[&Element], packet: &Packet) -> Result<(), ::failure::Error> {
+    for elem in elements.iter().filter(|e| e.compute().is_some()) {
+        for field in elements.iter().filter(|e| elem.compute_range().contains(e.name())) {
+            if let Some(unstable) = unstable_field(field, packet, &mut Vec::new()) {
+                return Err(::failure::err_msg(format!("range of computed element {} cannot cover {}", elem.name(), unstable)));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn unstable_field(field: &Element, packet: &Packet, visited: &mut Vec<String>) -> Option<String> {
+    if field.padding().is_some() {
+        return Some(format!("{} field", field.name()));
+    }
+    if field.map().as_ref().is_some_and(|map| !map.ordered) {
+        return Some(format!("unordered map {}", field.name()));
+    }
+    if visited.contains(field.type_()) {
+        return None;
+    }
+    visited.push(field.type_().clone());
+    let elements = packet.contents().iter().find_map(|content| match content {
+        PacketContent::Complex(ref c) if c.name() == field.type_() => match c.content() {
+            ComplexTypeContent::Seq(ref s) => Some(s.elements()),
+            ComplexTypeContent::Choice(ref choice) => Some(choice.elements()),
+            _ => None
+        },
+        _ => None
+    })?;
+    elements.iter().find_map(|elem| unstable_field(elem, packet, visited))
+}
+
+// scaled types convert from and to a raw integer, so they cannot also be enums or strings
+fn check_scale(restrict: &Restriction, name: &str) -> Result<bool, ::failure::Error> {
+    let scale = match restrict.scale() {
+        Some(ref scale) => scale,
+        None => return Ok(false)
+    };
+    if !is_integer_type(restrict.base()) {
+        return Err(::failure::err_msg(format!("scaled type {} must have an integer base, not {}", name, restrict.base())));
+    }
+    if restrict.flags() || !restrict.contents().is_empty() {
+        return Err(::failure::err_msg(format!("scaled type {} cannot have enumerations or restrictions", name)));
+    }
+    match (scale.factor.parse::<f64>(), scale.offset.parse::<f64>()) {
+        (Ok(factor), Ok(_)) if factor != 0.0 => Ok(true),
+        _ => Err(::failure::err_msg(format!("scaled type {} needs a non-zero numeric scale and a numeric offset", name)))
+    }
+}
+
+fn check_padding(elem: &Element) -> Result<(), ::failure::Error> {
+    let fill = match elem.padding() {
+        Some(ref fill) => fill,
+        None => return Ok(())
+    };
+    if !is_integer_type(elem.type_()) {
+        return Err(::failure::err_msg(format!("reserved field must have an integer type, not {}", elem.type_())));
+    }
+    match parse_integer(fill) {
+        Some(value) if integer_range(elem.type_()).contains(&value) => Ok(()),
+        Some(_) => Err(::failure::err_msg(format!("fill value {} of {} does not fit in {}", fill, elem.name(), elem.type_()))),
+        None => Err(::failure::err_msg(format!("fill value {} of {} is not an integer", fill, elem.name())))
+    }
+}
+
+// a fixed length blob is read with a constant count, so it cannot also carry its size
+fn check_blob(elem: &Element) -> Result<(), ::failure::Error> {
+    if elem.blob_length().is_some() && elem.size_occurs().is_some() {
+        return Err(::failure::err_msg(format!("bytes element {} cannot have both a length and an occursSize", elem.name())));
+    }
+    Ok(())
+}
+
+// map pairs are read and written with the plain reader and writer calls
+fn check_map(elem: &Element) -> Result<(), ::failure::Error> {
+    let map = match elem.map() {
+        Some(ref map) => map,
+        None => return Ok(())
+    };
+    if !is_integer_type(&map.key) && map.key != "std::string" {
+        return Err(::failure::err_msg(format!("map element {} must have an integer or string key, not {}", elem.name(), map.key)));
+    }
+    let value = is_integer_type(&map.value) || match map.value.as_str() {
+        "char" | "float" | "double" | "std::string" => true,
+        _ => false
+    };
+    if !value {
+        return Err(::failure::err_msg(format!("map element {} must have a builtin value type, not {}", elem.name(), map.value)));
+    }
+    match elem.size_occurs() {
+        Some(ref size) if is_integer_type(size) => Ok(()),
+        _ => Err(::failure::err_msg(format!("map element {} needs an integer occursSize", elem.name())))
+    }
+}
+
+fn is_integer_type(type_: &str) -> bool {
+    match type_ {
+        "int8_t" | "uint8_t" | "int16_t" | "uint16_t" | "int32_t" | "uint32_t" | "int64_t" | "uint64_t" => true,
+        _ => false
+    }
+}
+
+// nested unbounded levels can only be read back if their size is written out
+fn check_inner_occurs(elem: &Element) -> Result<(), ::failure::Error> {
+    if elem.inner_occurs().is_empty() {
+        return Ok(());
+    }
+    if elem.occurs() == &Some(Occurs::Unbounded) && elem.size_occurs().is_none() && elem.occurs_ref().is_none() {
+        return Err(::failure::err_msg(format!("multi-dimensional element {} needs an occursSize or occursRef", elem.name())));
+    }
+    if elem.bits().is_some() {
+        return Err(::failure::err_msg(format!("multi-dimensional element {} cannot be a bitfield", elem.name())));
+    }
+    for inner in elem.inner_occurs() {
+        if inner.occurs == Occurs::Unbounded && inner.size_occurs.is_none() {
+            return Err(::failure::err_msg(format!("unbounded level of element {} needs an occursSize", elem.name())));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ::flat_ast::Packet;
+    use ::flatten::{self, IncludeCache, Options, PacketNames};
+    use std::path::Path;
+
+    fn run(xml: &str) -> Result<Packet, ::failure::Error> {
+        let packet = ::schema::Reader::load_packet(::std::io::Cursor::new(xml.to_owned()))?;
+        let (cache, packets) = (IncludeCache::default(), PacketNames::new());
+        let options = Options { version: None, profiles: &[], shared: false, include_dirs: &[], cache: &cache, packets: &packets };
+        let (packet, _) = flatten::flatten(Path::new("test.xml"), &packet, &options)?;
+        super::run(packet)
+    }
+
+    fn error(xml: &str) -> String {
+        run(xml).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn optional_without_presence() {
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="hp" type="uint16_t" optional="true"/>
+        </packet>"#), "optional element hp requires a presence mask");
+    }
+
+    #[test]
+    fn presence_too_small() {
+        let elements = (0..9).map(|i| format!(r#"<element name="hp{}" type="uint16_t" optional="true"/>"#, i)).collect::<String>();
+        assert_eq!(error(&format!(r#"<packet ePacketType="PAKCS_PACKET" presence="uint8_t">{}</packet>"#, elements)),
+            "presence mask uint8_t cannot hold 9 optional elements");
+    }
+
+    #[test]
+    fn optional_bitfield() {
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET" presence="uint8_t">
+            <element name="hp" type="uint16_t" bits="4" optional="true"/>
+            <element name="mp" type="uint16_t" bits="4"/>
+        </packet>"#), "optional element hp cannot be a bitfield");
+    }
+
+    fn element<'a>(packet: &'a Packet, name: &str) -> &'a ::flat_ast::Element {
+        packet.contents().iter().filter_map(|content| match content {
+            ::flat_ast::PacketContent::Element(ref elem) => Some(&**elem),
+            _ => None
+        }).find(|elem| elem.name() == name).unwrap()
+    }
+
+    #[test]
+    fn occurs_ref() {
+        let packet = run(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="count" type="uint8_t"/>
+            <element name="items" type="uint32_t" occurs="unbounded" occursRef="count"/>
+        </packet>"#).unwrap();
+        assert_eq!(*element(&packet, "count").count_for(), Some("items".to_owned()));
+    }
+
+    #[test]
+    fn occurs_ref_errors() {
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="items" type="uint32_t" occurs="unbounded" occursRef="count"/>
+            <element name="count" type="uint8_t"/>
+        </packet>"#), "occursRef count of element items must name an earlier element");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="count" type="uint8_t"/>
+            <element name="items" type="uint32_t" occurs="4" occursRef="count"/>
+        </packet>"#), "element items with occursRef must be unbounded");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="count" type="uint8_t"/>
+            <element name="items" type="uint32_t" occurs="unbounded" occursRef="count"/>
+            <element name="others" type="uint32_t" occurs="unbounded" occursRef="count"/>
+        </packet>"#), "element count cannot be used as the count of others");
+    }
+
+    #[test]
+    fn nested_occurs_errors() {
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="grid" type="uint8_t" occurs="unbounded,4"/>
+        </packet>"#), "multi-dimensional element grid needs an occursSize or occursRef");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="grid" type="uint8_t" occurs="4,unbounded"/>
+        </packet>"#), "unbounded level of element grid needs an occursSize");
+    }
+
+    #[test]
+    fn condition_errors() {
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="a" type="uint8_t" if="kind == 1"/>
+            <element name="kind" type="uint8_t"/>
+        </packet>"#), "condition field kind of element a must name an earlier element");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="kind" type="uint8_t" occurs="4"/>
+            <element name="a" type="uint8_t" if="kind == 1"/>
+        </packet>"#), "element kind cannot be used in the condition of a");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <complexType name="Either">
+                <choice>
+                    <element name="kind" type="uint8_t"/>
+                    <element name="a" type="uint8_t" if="kind == 1"/>
+                </choice>
+            </complexType>
+            <element name="value" type="Either"/>
+        </packet>"#), "conditional element a cannot be part of a choice");
+    }
+
+    #[test]
+    fn fixed_values() {
+        let fixed = |type_: &str, value: &str| run(&format!(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="magic" type="{}" fixed="{}"/>
+        </packet>"#, type_, value)).map(|_| ()).map_err(|e| e.to_string());
+        assert_eq!(fixed("uint32_t", "0xCAFE"), Ok(()));
+        assert_eq!(fixed("int8_t", "-128"), Ok(()));
+        assert_eq!(fixed("uint8_t", "256"), Err("fixed value 256 of element magic does not fit in uint8_t".to_owned()));
+        assert_eq!(fixed("uint16_t", "-1"), Err("fixed value -1 of element magic does not fit in uint16_t".to_owned()));
+        assert_eq!(fixed("uint16_t", "MAGIC"), Err("fixed value MAGIC of element magic is not an integer".to_owned()));
+        assert_eq!(fixed("uint16_t", "0x"), Err("fixed value 0x of element magic is not an integer".to_owned()));
+        assert_eq!(fixed("float", "1"), Err("fixed element magic must have an integer type, not float".to_owned()));
+    }
+
+    #[test]
+    fn compute_ranges() {
+        let packet = run(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="size" type="uint16_t" compute="length(body..tail)"/>
+            <element name="body" type="uint8_t"/>
+            <element name="tail" type="uint32_t"/>
+            <element name="sum" type="uint8_t" compute="sum8"/>
+        </packet>"#).unwrap();
+        assert_eq!(*element(&packet, "size").compute_range(), vec!["body".to_owned(), "tail".to_owned()]);
+        assert_eq!(*element(&packet, "sum").compute_range(), vec!["size".to_owned(), "body".to_owned(), "tail".to_owned()]);
+    }
+
+    #[test]
+    fn compute_errors() {
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="body" type="uint8_t"/>
+            <element name="size" type="uint16_t" compute="length"/>
+        </packet>"#), "computed length size needs a range");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="body" type="uint8_t"/>
+            <element name="size" type="uint16_t" compute="length(body..size)"/>
+        </packet>"#), "range of computed element size is empty or contains itself");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="sum" type="uint8_t" compute="sum8(body)"/>
+            <element name="low" type="uint8_t" bits="4"/>
+            <element name="high" type="uint8_t" bits="4"/>
+            <element name="body" type="uint8_t"/>
+            <element name="crc" type="uint16_t" compute="crc16(high..body)"/>
+        </packet>"#), "range of computed element crc cannot cover only part of a bitfield set");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="body" type="uint8_t"/>
+            <element name="size" type="uint16_t" compute="length(body)"/>
+            <element name="sum" type="uint8_t" compute="sum8(body..size)"/>
+            <element name="crc" type="uint16_t" compute="crc16(body..sum)"/>
+        </packet>"#), "computed element crc cannot cover sum which covers other computed elements");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="body" type="uint8_t"/>
+            <element name="sum" type="float" compute="sum8"/>
+        </packet>"#), "computed element sum must have an integer type");
+    }
+
+    #[test]
+    fn compute_rewritten_fields() {
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="body" type="uint8_t"/>
+            <padding bytes="2"/>
+            <element name="sum" type="uint8_t" compute="sum8"/>
+        </packet>"#), "range of computed element sum cannot cover padding field");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <map name="items" keyType="uint16_t" valueType="uint32_t" occursSize="uint8_t"/>
+            <element name="crc" type="uint16_t" compute="crc16(items)"/>
+        </packet>"#), "range of computed element crc cannot cover unordered map items");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <complexType name="stats">
+                <sequence>
+                    <element name="hp" type="uint16_t"/>
+                    <reserved type="uint16_t"/>
+                </sequence>
+            </complexType>
+            <element name="values" type="stats"/>
+            <element name="sum" type="uint8_t" compute="sum8(values)"/>
+        </packet>"#), "range of computed element sum cannot cover reserved field");
+        assert!(run(r#"<packet ePacketType="PAKCS_PACKET">
+            <map name="items" keyType="uint16_t" valueType="uint32_t" occursSize="uint8_t" ordered="true"/>
+            <padding bytes="2"/>
+            <element name="crc" type="uint16_t" compute="crc16(items)"/>
+        </packet>"#).is_ok());
+    }
+
+    #[test]
+    fn scale_errors() {
+        let scaled = |base: &str, scale: &str| error(&format!(r#"<packet ePacketType="PAKCS_PACKET">
+            <simpleType name="Speed">
+                <restriction base="{}" scale="{}"/>
+            </simpleType>
+            <element name="speed" type="Speed"/>
+        </packet>"#, base, scale));
+        assert_eq!(scaled("float", "2"), "scaled type speed must have an integer base, not float");
+        assert_eq!(scaled("uint16_t", "0"), "scaled type speed needs a non-zero numeric scale and a numeric offset");
+        assert_eq!(scaled("uint16_t", "fast"), "scaled type speed needs a non-zero numeric scale and a numeric offset");
+    }
+
+    #[test]
+    fn padding_fills() {
+        let reserved = |type_: &str, fill: &str| run(&format!(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="hp" type="uint16_t"/>
+            <reserved type="{}" fill="{}"/>
+        </packet>"#, type_, fill)).map(|_| ()).map_err(|e| e.to_string());
+        assert_eq!(reserved("uint16_t", "0xFFFF"), Ok(()));
+        assert_eq!(reserved("int8_t", "-1"), Ok(()));
+        assert_eq!(reserved("uint16_t", "0x10000"), Err("fill value 0x10000 of reserved does not fit in uint16_t".to_owned()));
+        assert_eq!(reserved("uint8_t", "-1"), Err("fill value -1 of reserved does not fit in uint8_t".to_owned()));
+        assert_eq!(reserved("uint32_t", "0xFF); abort("), Err("fill value 0xFF); abort( of reserved is not an integer".to_owned()));
+        assert_eq!(reserved("float", "0"), Err("reserved field must have an integer type, not float".to_owned()));
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <padding bytes="4" fill="256"/>
+        </packet>"#), "fill value 256 of padding does not fit in uint8_t");
+    }
+
+    #[test]
+    fn blob_errors() {
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <bytes name="key" length="16" occursSize="uint8_t"/>
+        </packet>"#), "bytes element key cannot have both a length and an occursSize");
+    }
+
+    #[test]
+    fn map_errors() {
+        let map = |key: &str, value: &str, size: &str| error(&format!(r#"<packet ePacketType="PAKCS_PACKET">
+            <map name="items" keyType="{}" valueType="{}" occursSize="{}"/>
+        </packet>"#, key, value, size));
+        assert_eq!(map("float", "uint32_t", "uint8_t"), "map element items must have an integer or string key, not float");
+        assert_eq!(map("uint16_t", "Item", "uint8_t"), "map element items must have a builtin value type, not Item");
+        assert_eq!(map("uint16_t", "uint32_t", "float"), "map element items needs an integer occursSize");
+        assert_eq!(error(r#"<packet ePacketType="PAKCS_PACKET">
+            <complexType name="Either">
+                <choice>
+                    <element name="a" type="uint8_t"/>
+                    <map name="items" keyType="uint16_t" valueType="uint32_t" occursSize="uint8_t"/>
+                </choice>
+            </complexType>
+            <element name="value" type="Either"/>
+        </packet>"#), "map element items cannot be part of a choice");
+    }
+
+    fn typed(types: &[(&str, ::flat_ast::Origin)]) -> Packet {
+        let mut packet = Packet::new("PAKCS_PACKET".to_owned(), None);
+        packet.add_content(::flat_ast::PacketContent::Include("bitset".to_owned(), true));
+        for (name, origin) in types {
+            let mut simple = ::flat_ast::SimpleType::new(name.to_string(), None);
+            simple.set_origin(origin.clone());
+            packet.add_content(::flat_ast::PacketContent::Simple(simple));
+        }
+        packet
+    }
+
+    #[test]
+    fn merge_shared() {
+        use ::flat_ast::{Origin, PacketContent};
+        let (common, other) = (Origin::shared("common.xml".to_owned()), Origin::shared("other.xml".to_owned()));
+        let import = Origin { path: "lib.xml".to_owned(), namespace: "LibTypes".to_owned(), filename: "lib_types".to_owned() };
+        let mut types = Packet::new(::flat_ast::SHARED_TYPES_NAMESPACE.to_owned(), None);
+        super::merge_shared(&mut types, &typed(&[("hp", common.clone())])).unwrap();
+        super::merge_shared(&mut types, &typed(&[("hp", common.clone()), ("mp", common.clone()), ("pos", import.clone())])).unwrap();
+        let names = types.contents().iter().filter_map(PacketContent::type_from_name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["hp", "mp", "pos"]);
+        assert_eq!(types.contents().iter().filter(|content| matches!(content, PacketContent::Include(..))).count(), 1);
+        // the same name from another import is not a conflict, both are only referred to
+        super::merge_shared(&mut types, &typed(&[("pos", Origin { path: "other_lib.xml".to_owned(), ..import })])).unwrap();
+        assert_eq!(super::merge_shared(&mut types, &typed(&[("hp", other)])).unwrap_err().to_string(),
+            "shared type hp is defined in both common.xml and other.xml");
+    }
+}
@@ -4,16 +4,31 @@ extern crate heck;
 extern crate clap;
 #[macro_use] extern crate log;
 extern crate simple_logger;
+#[macro_use] extern crate serde;
+extern crate toml;
+extern crate glob;
+extern crate similar;
+extern crate fnv;
 
 mod flat_ast;
 mod flatten;
 mod writer;
 mod codegen;
 mod graph_passes;
+mod cache;
+mod depfile;
+mod manifest;
+mod config;
+#[cfg(test)]
+mod testing;
 
-use codegen::{cpp, Codegen, CodegenCommands};
+use codegen::{cpp, Codegen, CodegenCommands, GeneratedFile};
 
 use log::Level;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
@@ -21,13 +36,59 @@ use clap::Parser;
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    /// Project file, `idl.toml` when it exists (its keys are overridden by the flags of the same name)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Input files, directories (searched recursively for xml files) and globs
     #[arg(short, long)]
     inputs: Vec<String>,
+    /// The backend to run, every one enabled in the project file otherwise
     #[command(subcommand)]
-    command: codegen::CodegenCommands,
+    command: Option<codegen::CodegenCommands>,
     #[arg(short, long, action = clap::ArgAction::Count)]
-    verbose: u8
-
+    verbose: u8,
+    /// Only keep the packets, types and elements present in this protocol version (defaults to the newest one)
+    #[arg(long)]
+    protocol_version: Option<u32>,
+    /// Only keep the packets, types and elements without a profile or tagged with one of these (defaults to all of them)
+    #[arg(long)]
+    profile: Vec<String>,
+    /// Generate the types of included and imported files once in shared files instead of in every packet
+    #[arg(long, overrides_with = "no_shared_types")]
+    shared_types: bool,
+    /// Generate the types in every packet even when the project file enables shared_types
+    #[arg(long)]
+    no_shared_types: bool,
+    /// Directories to look for included and imported files in when they are not found next to the input file
+    #[arg(short = 'I', long)]
+    include_dir: Vec<std::path::PathBuf>,
+    /// Number of packets generated at once (defaults to the number of cores)
+    #[arg(short, long)]
+    jobs: Option<usize>,
+    /// Skip the inputs unchanged since the run that wrote this cache file (not used with --shared-types)
+    #[arg(long)]
+    cache: Option<PathBuf>,
+    /// Write a Makefile-style depfile of the generated files and the xml files they come from
+    #[arg(long)]
+    depfile: Option<PathBuf>,
+    /// Write a CMake script listing the generated files and the xml files they come from
+    #[arg(long)]
+    cmake: Option<PathBuf>,
+    /// Only generate the packets of these types (their ePacketType), to regenerate a subset of them
+    #[arg(long)]
+    packet: Vec<String>,
+    /// Skip the packets of these types (their ePacketType)
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// Remove the files of the output directories generated by a previous run and not anymore
+    #[arg(long, overrides_with = "no_clean_stale")]
+    clean_stale: bool,
+    /// Keep the stale files even when the project file enables clean_stale
+    #[arg(long)]
+    no_clean_stale: bool,
+    /// Only check the generated files are up to date, printing a diff of the ones that are not, without writing anything
+    #[arg(long)]
+    verify: bool
 }
 
 fn main() -> Result<(), failure::Error> {
@@ -40,24 +101,549 @@ fn main() -> Result<(), failure::Error> {
     };
 
     simple_logger::init_with_level(verbose).unwrap();
+    run(args)
+}
 
+fn run(mut args: Args) -> Result<(), failure::Error> {
+    let backends = configure(&mut args)?;
+
+    let mut types = flat_ast::Packet::new("TYPES".to_owned(), None);
+    types.set_class_name(flat_ast::SHARED_TYPES_NAMESPACE.to_owned());
+    types.set_filename(flat_ast::SHARED_TYPES_FILENAME.to_owned());
+
+    // the shared types need every packet, so nothing can be skipped with them
+    let mut cache = match args.cache {
+        Some(_) if args.verify => None,
+        Some(_) if args.shared_types => {
+            warn!("The cache is not used with --shared-types");
+            None
+        },
+        Some(ref path) => {
+            let salt = format!("{} {:?} {:?} {:?} {:?}", VERSION, args.protocol_version, args.profile, args.include_dir, backends);
+            Some(cache::Cache::load(path, salt))
+        },
+        None => None
+    };
+
+    let filtered = !args.packet.is_empty() || !args.exclude.is_empty();
+    let mut errors = Vec::new();
+    let mut jobs = Vec::new();
+    // the files each input depends on and the ones generated from it, `None` once one of its packets failed
+    let mut inputs = HashMap::new();
+    // the files without any packet, only there to be included or imported
+    let mut include_only = Vec::new();
+    // the inputs with packets left out by --packet or --exclude, their cache entry is kept as is
+    let mut partial = HashSet::new();
+    let mut selected = HashSet::new();
+    let mut skipped = false;
+    let mut names = flatten::PacketNames::new();
     for filename in args.inputs.iter().map(std::path::Path::new) {
         debug!("filename {:?}", filename);
-        use std::fs::File;
-        let file = File::open(filename)?;
-        let packet = schema::Reader::load_packet(file)?;
-        if packet.type_() == "tmp" {
+        if let Some(cache) = cache.as_ref().filter(|cache| cache.is_fresh(filename)) {
+            info!("Skipped {}, unchanged since the last run", filename.display());
+            for (type_, class_name, file_name) in cache.packets(filename) {
+                names.insert(type_, (class_name, file_name, filename.to_owned()));
+            }
+            skipped = true;
+            continue;
+        }
+        let packets = std::fs::File::open(filename).map_err(failure::Error::from).and_then(schema::Reader::load_packets);
+        match packets {
+            Ok(ref packets) if packets.is_empty() => {
+                debug!("{} is include-only", filename.display());
+                include_only.push(filename);
+            },
+            Ok(packets) => {
+                inputs.insert(filename, Some((Vec::new(), Vec::new())));
+                for packet in packets {
+                    if !packet.availability().includes(args.protocol_version, &args.profile) {
+                        info!("Skipped packet {} not in this protocol version or profile", packet.type_());
+                        continue;
+                    }
+                    let (class_name, file_name) = flatten::packet_names(&packet);
+                    names.insert(packet.type_().to_owned(), (class_name, file_name, filename.to_owned()));
+                    if !is_selected(&args, packet.type_()) {
+                        debug!("Skipped packet {}, filtered out", packet.type_());
+                        partial.insert(filename);
+                        continue;
+                    }
+                    selected.insert(packet.type_().to_owned());
+                    jobs.push((filename, packet));
+                }
+            },
+            Err(e) => {
+                errors.push(format!("{}: {}", filename.display(), e));
+                inputs.insert(filename, None);
+            }
+        }
+    }
+
+    // a cached input may hold the packet
+    if !skipped {
+        for name in args.packet.iter().filter(|name| !selected.contains(*name)) {
+            warn!("No packet {} in the inputs", name);
+        }
+    }
+
+    let includes = flatten::IncludeCache::default();
+    let options = flatten::Options {
+        version: args.protocol_version,
+        profiles: &args.profile,
+        shared: args.shared_types,
+        include_dirs: &args.include_dir,
+        cache: &includes,
+        packets: &names
+    };
+
+    // packets are generated in parallel, the results are kept in the input order
+    let threads = args.jobs.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+        .clamp(1, jobs.len().max(1));
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(jobs.iter().map(|_| None).collect::<Vec<_>>());
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let (filename, packet) = match jobs.get(index) {
+                    Some(job) => job,
+                    None => break
+                };
+                let result = generate(filename, packet, &options, &backends, args.verify);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    let mut differences = Vec::new();
+    // the imported files whose types the packets use, by the inputs using them
+    let mut imports = HashMap::new();
+    for ((filename, packet), result) in jobs.iter().zip(results.into_inner().unwrap()) {
+        let merged = result.unwrap().and_then(|(generated, dependencies, outputs, different)| {
+            graph_passes::merge_shared(&mut types, &generated)?;
+            for path in imported_files(&generated) {
+                imports.entry(path).or_insert_with(HashSet::new).insert(*filename);
+            }
+            differences.extend(different);
+            Ok((dependencies, outputs))
+        });
+        match merged {
+            Ok((dependencies, outputs)) => if let Some(Some((ref mut files, ref mut generated))) = inputs.get_mut(filename) {
+                files.extend(dependencies);
+                generated.extend(outputs);
+            },
+            Err(e) => {
+                errors.push(format!("{} ({}): {}", packet.type_(), filename.display(), e));
+                inputs.insert(filename, None);
+            }
+        }
+    }
+
+    // the types of every imported file are generated once, for all the packets using them,
+    // which also need the files they import in turn
+    let mut pending = imports.keys().cloned().collect::<Vec<_>>();
+    let mut import_outputs = HashMap::new();
+    let mut nested = HashMap::new();
+    let mut import_files = HashMap::new();
+    while let Some(path) = pending.pop() {
+        if import_outputs.contains_key(&path) {
             continue;
         }
-        let packet = flatten::flatten(filename.parent().unwrap_or(std::path::Path::new("./")), &packet)?;
-        trace!("packet {:?}", packet);
-        let packet = graph_passes::run(packet)?;
-        debug!("packet {:#?}", packet);
-        let mut generator = match &args.command {
+        match generate_import(&path, &options, &backends, args.verify) {
+            Ok((generated, _, outputs, different)) => {
+                if let Some(other) = import_files.insert(generated.filename().clone(), path.clone()) {
+                    errors.push(format!("{} and {} are both imported, their types would be generated in the same {} files",
+                        other.display(), path.display(), generated.filename()));
+                }
+                let files = imported_files(&generated);
+                pending.extend(files.iter().cloned());
+                nested.insert(path.clone(), files);
+                differences.extend(different);
+                import_outputs.insert(path, outputs);
+            },
+            Err(e) => {
+                errors.push(format!("{}: {}", path.display(), e));
+                import_outputs.insert(path, Vec::new());
+            }
+        }
+    }
+    for (path, users) in &imports {
+        let mut seen = HashSet::new();
+        let mut stack = vec![path.clone()];
+        while let Some(path) = stack.pop() {
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+            stack.extend(nested.get(&path).cloned().unwrap_or_default());
+            for filename in users {
+                if let Some(Some((_, ref mut generated))) = inputs.get_mut(filename) {
+                    for output in &import_outputs[&path] {
+                        if !generated.contains(output) {
+                            generated.push(output.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut dependencies = depfile::Dependencies::default();
+    let mut manifests = manifest::Manifests::default();
+    let mut referenced = HashSet::new();
+    for filename in args.inputs.iter().map(std::path::Path::new) {
+        let generated = match inputs.get_mut(filename) {
+            Some(Some((ref mut files, ref outputs))) => {
+                files.sort();
+                files.dedup();
+                let input = filename.canonicalize().unwrap_or_else(|_| filename.to_owned());
+                let mut all = vec![input.clone()];
+                all.extend(files.iter().filter(|file| **file != input).cloned());
+                Some((all, outputs.clone()))
+            },
+            Some(None) => None,
+            None => cache.as_ref().and_then(|cache| cache.entry(filename))
+        };
+        if let Some((files, outputs)) = generated {
+            referenced.extend(files.iter().cloned());
+            manifests.add(&outputs);
+            dependencies.add(filename, files, outputs);
+        }
+    }
+
+    // the packets left out or failed may reference them
+    if !filtered && errors.is_empty() {
+        for filename in include_only {
+            if !referenced.contains(&filename.canonicalize().unwrap_or_else(|_| filename.to_owned())) {
+                warn!("{} has no packet and no packet includes or imports it", filename.display());
+            }
+        }
+    }
+
+    if let Some(ref mut cache) = cache {
+        for (filename, generated) in inputs {
+            if partial.contains(filename) {
+                continue;
+            }
+            match generated {
+                Some((files, outputs)) => {
+                    let packets = names.iter().filter(|(_, (_, _, input))| input == filename)
+                        .map(|(type_, (class_name, file_name, _))| (type_.clone(), class_name.clone(), file_name.clone()))
+                        .collect();
+                    cache.update(filename, &files, outputs, packets)
+                },
+                None => cache.remove(filename)
+            }
+        }
+        cache.save()?;
+    }
+
+    if types.contents().iter().filter_map(flat_ast::PacketContent::origin).any(|origin| origin.namespace == flat_ast::SHARED_TYPES_NAMESPACE) {
+        // only the files the shared types come from, the other inputs do not change them
+        let mut files = types.contents().iter().filter_map(flat_ast::PacketContent::origin)
+            .filter(|origin| origin.namespace == flat_ast::SHARED_TYPES_NAMESPACE)
+            .map(|origin| PathBuf::from(&origin.path))
+            .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        let input_hash = codegen::input_hash(&files)?;
+        let mut outputs = Vec::new();
+        for backend in &backends {
+            let mut generator = match backend {
+                CodegenCommands::CppCommand(args) => cpp::Generator::new(args)
+            };
+            let (generated, different) = emit(generator.generate_types(VERSION, input_hash, &types)?, args.verify)?;
+            outputs.extend(generated);
+            differences.extend(different);
+        }
+        manifests.add(&outputs);
+        dependencies.add_shared(outputs);
+        info!("Generated shared types");
+    }
+
+    if !errors.is_empty() {
+        for e in &errors {
+            error!("{}", e);
+        }
+        return Err(failure::err_msg(format!("generation failed with {} error(s)", errors.len())));
+    }
+
+    if args.verify {
+        if !differences.is_empty() {
+            for difference in &differences {
+                print!("{}", difference);
+            }
+            return Err(failure::err_msg(format!("{} generated file(s) out of date", differences.len())));
+        }
+        info!("The generated files are up to date");
+        return Ok(());
+    }
+
+    for backend in &backends {
+        let generator = match backend {
             CodegenCommands::CppCommand(args) => cpp::Generator::new(args)
         };
-        generator.generate(VERSION, &packet)?;
-        info!("Generated packet {}", packet.type_());
+        for dir in generator.output_dirs() {
+            manifests.add_dir(&dir);
+        }
+    }
+    if args.clean_stale && filtered {
+        warn!("No file is removed with --packet or --exclude, as not every packet is generated");
+    }
+    manifests.write(VERSION, filtered, args.clean_stale)?;
+
+    if let Some(ref path) = args.depfile {
+        dependencies.write_depfile(path)?;
+    }
+    if let Some(ref path) = args.cmake {
+        dependencies.write_cmake(path, VERSION)?;
     }
     Ok(())
 }
+
+// the packet, the files it was generated from besides its input, the generated ones
+// and with --verify the diffs of the ones out of date
+type Generated = (flat_ast::Packet, Vec<PathBuf>, Vec<PathBuf>, Vec<String>);
+
+fn generate(filename: &Path, packet: &schema::ast::Packet, options: &flatten::Options, backends: &[CodegenCommands], verify: bool) -> Result<Generated, failure::Error> {
+    let (packet, dependencies) = flatten::flatten(filename, packet, options)?;
+    trace!("packet {:?}", packet);
+    let packet = graph_passes::run(packet)?;
+    debug!("packet {:#?}", packet);
+    let input = filename.canonicalize().unwrap_or_else(|_| filename.to_owned());
+    let mut files = vec![input.clone()];
+    files.extend(dependencies.iter().filter(|file| **file != input).cloned());
+    let input_hash = codegen::input_hash(&files)?;
+    let mut outputs = Vec::new();
+    let mut differences = Vec::new();
+    for backend in backends {
+        let mut generator = match backend {
+            CodegenCommands::CppCommand(args) => cpp::Generator::new(args)
+        };
+        let (generated, different) = emit(generator.generate(VERSION, input_hash, &packet)?, verify)?;
+        outputs.extend(generated);
+        differences.extend(different);
+    }
+    info!("Generated packet {}", packet.type_());
+    Ok((packet, dependencies, outputs, differences))
+}
+
+// the types of an imported file, generated once for all the packets importing it
+fn generate_import(path: &Path, options: &flatten::Options, backends: &[CodegenCommands], verify: bool) -> Result<Generated, failure::Error> {
+    let (packet, dependencies) = flatten::flatten_import(path, options)?;
+    let packet = graph_passes::run_types(packet)?;
+    debug!("imported types {:#?}", packet);
+    let input_hash = codegen::input_hash(&dependencies)?;
+    let mut outputs = Vec::new();
+    let mut differences = Vec::new();
+    for backend in backends {
+        let mut generator = match backend {
+            CodegenCommands::CppCommand(args) => cpp::Generator::new(args)
+        };
+        let (generated, different) = emit(generator.generate_types(VERSION, input_hash, &packet)?, verify)?;
+        outputs.extend(generated);
+        differences.extend(different);
+    }
+    info!("Generated the types of {}", path.display());
+    Ok((packet, dependencies, outputs, differences))
+}
+
+// the packets kept by --packet and --exclude
+fn is_selected(args: &Args, type_: &str) -> bool {
+    (args.packet.is_empty() || args.packet.iter().any(|name| name == type_)) && !args.exclude.iter().any(|name| name == type_)
+}
+
+// the imported files a packet uses types of, the shared types of included files aside
+fn imported_files(packet: &flat_ast::Packet) -> Vec<PathBuf> {
+    let mut files = packet.contents().iter().filter_map(flat_ast::PacketContent::origin)
+        .filter(|origin| origin.namespace != flat_ast::SHARED_TYPES_NAMESPACE)
+        .map(|origin| PathBuf::from(&origin.path))
+        .collect::<Vec<_>>();
+    files.sort();
+    files.dedup();
+    files
+}
+
+// writes the files, or with `verify` compares them with the ones on disk, giving back their paths and the diffs
+fn emit(files: Vec<GeneratedFile>, verify: bool) -> Result<(Vec<PathBuf>, Vec<String>), failure::Error> {
+    let mut outputs = Vec::new();
+    let mut differences = Vec::new();
+    for file in files {
+        if verify {
+            differences.extend(codegen::verify(&file));
+        } else {
+            codegen::write_if_changed(&file.path, &file.contents)?;
+        }
+        outputs.push(file.path);
+    }
+    Ok((outputs, differences))
+}
+
+// completes the command line with the project file and gives the backends to run
+fn configure(args: &mut Args) -> Result<Vec<CodegenCommands>, failure::Error> {
+    let path = match args.config {
+        Some(ref path) => Some(path.clone()),
+        None => Some(PathBuf::from(config::DEFAULT_CONFIG)).filter(|path| path.is_file())
+    };
+    let config = match path {
+        Some(ref path) => {
+            debug!("Using the project file {}", path.display());
+            config::Config::load(path)?
+        },
+        None => config::Config::default()
+    };
+    let inputs = if args.inputs.is_empty() { config.inputs.clone() } else { std::mem::take(&mut args.inputs) };
+    args.inputs = config::input_files(&inputs)?;
+    if args.include_dir.is_empty() {
+        args.include_dir = config.include_dirs.clone();
+    }
+    if args.profile.is_empty() {
+        args.profile = config.profiles.clone();
+    }
+    args.protocol_version = args.protocol_version.or(config.protocol_version);
+    args.shared_types = !args.no_shared_types && (args.shared_types || config.shared_types);
+    args.clean_stale = !args.no_clean_stale && (args.clean_stale || config.clean_stale);
+    args.jobs = args.jobs.or(config.jobs);
+    args.cache = args.cache.take().or_else(|| config.cache.clone());
+    args.depfile = args.depfile.take().or_else(|| config.depfile.clone());
+    args.cmake = args.cmake.take().or_else(|| config.cmake.clone());
+    if args.packet.is_empty() {
+        args.packet = config.packets.clone();
+    }
+    if args.exclude.is_empty() {
+        args.exclude = config.exclude.clone();
+    }
+    if args.shared_types && (!args.packet.is_empty() || !args.exclude.is_empty()) {
+        return Err(failure::err_msg("--packet and --exclude cannot be used with --shared-types, the shared types need every packet"));
+    }
+
+    let backends = match args.command.take() {
+        Some(CodegenCommands::CppCommand(mut cpp)) => {
+            cpp.merge(config.cpp.as_ref())?;
+            vec![CodegenCommands::CppCommand(cpp)]
+        },
+        None => {
+            let mut backends = Vec::new();
+            if let Some(ref cpp) = config.cpp {
+                if cpp.enabled {
+                    backends.push(CodegenCommands::CppCommand(cpp::CppArgs::from_config(cpp)?));
+                }
+            }
+            backends
+        }
+    };
+    if backends.is_empty() {
+        return Err(failure::err_msg("no backend to run, pick one on the command line or enable one in the project file"));
+    }
+    Ok(backends)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{configure, is_selected, Args};
+    use clap::Parser;
+    use std::fs;
+
+    #[test]
+    fn config_merging() {
+        let dir = ::testing::temp_dir("main_config_merging");
+        let config = dir.join("idl.toml");
+        fs::write(&config, r#"
+            inputs = ["a.xml"]
+            profiles = ["server"]
+            protocol_version = 3
+            jobs = 4
+            shared_types = true
+            clean_stale = true
+
+            [cpp]
+            output_header_folder = "include"
+            output_source_folder = "src"
+            namespace = "Game"
+        "#).unwrap();
+        let parse = |flags: &[&str]| {
+            let mut args = Args::parse_from(["packet_generator", "--config", config.to_str().unwrap()].iter().chain(flags));
+            let backends = configure(&mut args).map(|backends| format!("{:?}", backends));
+            (args, backends)
+        };
+
+        let (args, backends) = parse(&[]);
+        assert_eq!(args.inputs, vec![dir.join("a.xml").display().to_string()]);
+        assert_eq!((args.profile, args.protocol_version, args.jobs), (vec!["server".to_owned()], Some(3), Some(4)));
+        assert!(args.shared_types && args.clean_stale);
+        let backends = backends.unwrap();
+        assert!(backends.contains(&format!("output_header_folder: Some({:?})", dir.join("include").display().to_string())));
+        assert!(backends.contains("namespace: Some(\"Game\")"));
+
+        // the flags win over the project file
+        let (args, backends) = parse(&["-i", "b.xml", "--profile", "client", "--protocol-version", "2", "cpp", "--namespace", "Other"]);
+        assert_eq!(args.inputs, vec!["b.xml".to_owned()]);
+        assert_eq!((args.profile, args.protocol_version, args.jobs), (vec!["client".to_owned()], Some(2), Some(4)));
+        let backends = backends.unwrap();
+        assert!(backends.contains("namespace: Some(\"Other\")"));
+        assert!(backends.contains(&format!("output_source_folder: Some({:?})", dir.join("src").display().to_string())));
+
+        let (args, _) = parse(&["--no-shared-types", "--no-clean-stale", "cpp"]);
+        assert!(!args.shared_types && !args.clean_stale);
+        // the last of a flag and its negation wins
+        let (args, _) = parse(&["--no-shared-types", "--shared-types", "--clean-stale", "--no-clean-stale", "cpp"]);
+        assert!(args.shared_types && !args.clean_stale);
+
+        fs::write(&config, "inputs = [\"a.xml\"]\n[cpp]\nenabled = false\n").unwrap();
+        assert_eq!(parse(&[]).1.unwrap_err().to_string(), "no backend to run, pick one on the command line or enable one in the project file");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn shared_types_hash() {
+        let dir = ::testing::temp_dir("main_shared_types_hash");
+        fs::write(dir.join("common.xml"), r#"<packet ePacketType="COMMON">
+            <simpleType name="hp"><restriction base="uint32_t"/></simpleType>
+        </packet>"#).unwrap();
+        fs::write(dir.join("a.xml"), r#"<packet ePacketType="PAKSC_A">
+            <includeXml path="common.xml"/>
+            <element name="hp" type="hp"/>
+        </packet>"#).unwrap();
+        fs::write(dir.join("b.xml"), r#"<packet ePacketType="PAKSC_B">
+            <element name="mp" type="uint32_t"/>
+        </packet>"#).unwrap();
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        let generate = || {
+            let args = Args::parse_from(["packet_generator", "--shared-types", "-i", dir.join("a.xml").to_str().unwrap(), "-i", dir.join("b.xml").to_str().unwrap(),
+                "cpp", "--output-header-folder", out.to_str().unwrap(), "--output-source-folder", out.to_str().unwrap()]);
+            super::run(args).unwrap();
+            fs::read(out.join("packet_types.h")).unwrap()
+        };
+
+        let types = generate();
+        // an input without shared types does not change them
+        fs::write(dir.join("b.xml"), r#"<packet ePacketType="PAKSC_B">
+            <element name="mp" type="uint16_t"/>
+        </packet>"#).unwrap();
+        assert_eq!(generate(), types);
+        // the file they come from does
+        fs::write(dir.join("common.xml"), r#"<packet ePacketType="COMMON">
+            <simpleType name="hp"><restriction base="uint32_t"/></simpleType>
+            <!-- the hit points -->
+        </packet>"#).unwrap();
+        assert_ne!(generate(), types);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn packet_filters() {
+        let args = |flags: &[&str]| Args::parse_from(["packet_generator"].iter().chain(flags));
+        let all = args(&[]);
+        assert!(is_selected(&all, "PAKCS_A"));
+        let only = args(&["--packet", "PAKCS_A", "--packet", "PAKCS_B", "--exclude", "PAKCS_B"]);
+        assert!(is_selected(&only, "PAKCS_A"));
+        assert!(!is_selected(&only, "PAKCS_B"));
+        assert!(!is_selected(&only, "PAKCS_C"));
+        let excluded = args(&["--exclude", "PAKCS_B"]);
+        assert!(is_selected(&excluded, "PAKCS_A"));
+        assert!(!is_selected(&excluded, "PAKCS_B"));
+
+        let mut shared = args(&["--shared-types", "--packet", "PAKCS_A", "cpp", "--output-header-folder", "out", "--output-source-folder", "out"]);
+        assert_eq!(configure(&mut shared).unwrap_err().to_string(),
+            "--packet and --exclude cannot be used with --shared-types, the shared types need every packet");
+    }
+}
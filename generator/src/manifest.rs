@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// written in every output directory, lists the files generated there
+pub const MANIFEST_FILENAME: &str = ".idl-manifest";
+
+// the files generated in every output directory, to find the ones a previous run generated and this one didn't
+#[derive(Default)]
+pub struct Manifests {
+    dirs: BTreeMap<PathBuf, BTreeSet<String>>
+}
+
+impl Manifests {
+    // an output directory, even when nothing is generated in it anymore
+    pub fn add_dir(&mut self, dir: &Path) {
+        self.dirs.entry(canonical_dir(dir)).or_default();
+    }
+
+    pub fn add(&mut self, outputs: &[PathBuf]) {
+        for output in outputs {
+            let name = match output.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue
+            };
+            self.dirs.entry(canonical_dir(output.parent().unwrap_or_else(|| Path::new(""))))
+                .or_default()
+                .insert(name);
+        }
+    }
+
+    // `partial` keeps the files of the previous run, as not every packet was generated.
+    // the stale files, generated by the previous run only, are removed with `clean` when they hold the banner,
+    // the others stay in the manifest so they are reported again
+    pub fn write(&self, version: &str, partial: bool, clean: bool) -> Result<(), ::failure::Error> {
+        for (dir, generated) in &self.dirs {
+            let path = dir.join(MANIFEST_FILENAME);
+            let mut files = generated.clone();
+            for name in read(&path).difference(generated) {
+                let file = dir.join(name);
+                if !file.is_file() {
+                    continue;
+                }
+                if partial {
+                    files.insert(name.clone());
+                } else if !clean {
+                    warn!("{} is not generated anymore, remove it with --clean-stale", file.display());
+                    files.insert(name.clone());
+                } else if !::codegen::is_generated(&file) {
+                    warn!("Not removing the stale {}, it has no generated-file banner", file.display());
+                    files.insert(name.clone());
+                } else {
+                    fs::remove_file(&file)
+                        .map_err(|e| ::failure::err_msg(format!("cannot remove {}: {}", file.display(), e)))?;
+                    info!("Removed the stale {}", file.display());
+                }
+            }
+            let mut contents = format!("# Generated with IDL v{}\n", version);
+            for name in files {
+                contents += &format!("{}\n", name);
+            }
+            ::codegen::write_if_changed(&path, contents.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> BTreeSet<String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => contents.lines().filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_owned).collect(),
+        Err(_) => BTreeSet::new()
+    }
+}
+
+fn canonical_dir(dir: &Path) -> PathBuf {
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    dir.canonicalize().unwrap_or_else(|_| dir.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Manifests, MANIFEST_FILENAME};
+    use std::fs;
+    use std::path::Path;
+
+    fn generated(names: &[&str], dir: &Path) -> Manifests {
+        let mut manifests = Manifests::default();
+        manifests.add_dir(dir);
+        manifests.add(&names.iter().map(|name| dir.join(name)).collect::<Vec<_>>());
+        manifests
+    }
+
+    fn listed(dir: &Path) -> Vec<String> {
+        super::read(&dir.join(MANIFEST_FILENAME)).into_iter().collect()
+    }
+
+    #[test]
+    fn stale_files() {
+        let dir = ::testing::temp_dir("manifest_stale_files");
+        let banner = format!("#pragma once\n\n/* {} */\n", ::codegen::banner("1.0", None));
+        for name in &["a.h", "b.h", "c.h"] {
+            fs::write(dir.join(name), &banner).unwrap();
+        }
+        generated(&["a.h", "b.h", "c.h"], &dir).write("1.0", false, true).unwrap();
+        assert_eq!(listed(&dir), vec!["a.h", "b.h", "c.h"]);
+
+        // without --clean-stale or with some packets filtered out, the stale files are only kept
+        generated(&["a.h", "b.h"], &dir).write("1.0", false, false).unwrap();
+        generated(&["a.h"], &dir).write("1.0", true, true).unwrap();
+        assert!(dir.join("c.h").is_file());
+        assert_eq!(listed(&dir), vec!["a.h", "b.h", "c.h"]);
+
+        // a file edited by hand and without the banner anymore is never removed
+        fs::write(dir.join("b.h"), "// mine now\n").unwrap();
+        generated(&["a.h"], &dir).write("1.0", false, true).unwrap();
+        assert!(!dir.join("c.h").exists());
+        assert!(dir.join("b.h").is_file());
+        assert_eq!(listed(&dir), vec!["a.h", "b.h"]);
+
+        // a listed file removed by hand is dropped from the manifest
+        fs::remove_file(dir.join("b.h")).unwrap();
+        generated(&["a.h"], &dir).write("1.0", false, false).unwrap();
+        assert_eq!(listed(&dir), vec!["a.h"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
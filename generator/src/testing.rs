@@ -0,0 +1,10 @@
+use std::fs;
+use std::path::PathBuf;
+
+// an empty directory of the test's own, cleared of what a previous run may have left in it
+pub fn temp_dir(test: &str) -> PathBuf {
+    let dir = ::std::env::temp_dir().join(format!("packet_generator_{}_{}", test, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir.canonicalize().unwrap()
+}
@@ -13,7 +13,6 @@ impl<T: Write> Writer<T> {
         }
     }
 
-    #[cfg(test)]
     pub fn into(self) -> T {
         self.writer
     }
@@ -1,41 +1,73 @@
 use std;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Packet {
     type_: String,
     contents: Vec<PacketContent>,
-    doc: Option<String>
+    doc: Option<String>,
+    presence: Option<String>,
+    availability: Availability,
+    direction: Option<Direction>,
+    class_name: Option<String>,
+    file_name: Option<String>
+}
+
+// an `<import path as>` file whose types are referenced as `alias::Type`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub path: String,
+    pub alias: String
 }
 
-#[derive(Debug)]
+// who sends a packet: the client, the server or another server
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Client,
+    Server,
+    Inter
+}
+
+#[derive(Debug, Clone)]
 pub enum PacketContent {
     IncludeXml(String),
+    Import(Import),
     Include(String, bool),
     SimpleType(SimpleType),
     ComplexType(ComplexType),
-    Element(Element)
+    Element(Box<Element>),
+    Padding(Padding)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SimpleType {
     name: String,
     contents: Vec<SimpleTypeContent>,
-    doc: Option<String>
+    doc: Option<String>,
+    availability: Availability
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SimpleTypeContent {
     Restriction(Restriction)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Restriction {
     base: String,
     doc: Option<String>,
-    contents: Vec<RestrictionContent>
+    contents: Vec<RestrictionContent>,
+    flags: bool,
+    scale: Option<Scale>
+}
+
+// a fixed-point type, the value is `raw * factor + offset`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub factor: String,
+    pub offset: String
 }
 
-#[derive(Debug, PartialOrd, Ord, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialOrd, Ord, PartialEq, Eq)]
 pub enum RestrictionContent {
     Enumeration(Enumeration),
     Length(u32),
@@ -43,11 +75,12 @@ pub enum RestrictionContent {
     MaxValue(String)
 }
 
-#[derive(Debug, PartialEq, Eq, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, Ord)]
 pub struct Enumeration {
     value: String,
     id: Option<i64>,
-    doc: Option<String>
+    doc: Option<String>,
+    bit: Option<u32>
 }
 
 impl std::cmp::PartialOrd for Enumeration {
@@ -56,43 +89,92 @@ impl std::cmp::PartialOrd for Enumeration {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ComplexType {
     name: String,
     content: ComplexTypeContent,
-    doc: Option<String>
+    doc: Option<String>,
+    availability: Availability
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ComplexTypeContent {
     Seq(Sequence),
     Choice(Choice),
     Empty
 }
 
+// the protocol versions (`since` and `until` are both inclusive) and build profiles a packet, type or element exists in
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Availability {
+    pub since: Option<u32>,
+    pub until: Option<u32>,
+    pub profiles: Vec<String>
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Occurs {
     Num(String),
     Unbounded
 }
 
-#[derive(Debug)]
+// a nested level of a multi-dimensional element, e.g. the `12` in `occurs="4,12"`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InnerOccurs {
+    pub occurs: Occurs,
+    pub size_occurs: Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConditionOp {
+    Eq,
+    Ne,
+    Mask
+}
+
+// an `if="field == VALUE"` guard on an element or sequence
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub field: String,
+    pub op: ConditionOp,
+    pub value: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComputeKind {
+    Length,
+    Crc16,
+    Sum8
+}
+
+// a `compute="crc16(start..end)"` field, filled in from the serialized bytes of the range
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compute {
+    pub kind: ComputeKind,
+    pub start: Option<String>,
+    pub end: Option<String>
+}
+
+#[derive(Debug, Clone)]
 pub struct Sequence {
     occurs: Option<Occurs>,
     size_occurs: Option<String>,
     contents: Vec<SequenceContent>,
     doc: Option<String>,
-    inline: bool
+    inline: bool,
+    presence: Option<String>,
+    condition: Option<Condition>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SequenceContent {
-    Element(Element),
+    Element(Box<Element>),
     Choice(Choice),
-    Seq(Sequence)
+    Seq(Sequence),
+    Padding(Padding)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Choice {
     occurs: Option<Occurs>,
     size_occurs: Option<String>,
@@ -107,7 +189,15 @@ pub enum ElementInitValue {
     None
 }
 
-#[derive(Debug)]
+// a `<map>` of `key` to the element's type, serialized as a counted list of pairs
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapType {
+    pub key: String,
+    pub value: String,
+    pub ordered: bool
+}
+
+#[derive(Debug, Clone)]
 pub struct Element {
     type_: ElementType,
     init: ElementInitValue,
@@ -117,28 +207,66 @@ pub struct Element {
     reference: bool,
     special_read_write: Option<String>,
     enum_type: Option<String>,
-    bits: Option<u32>
+    bits: Option<u32>,
+    optional: bool,
+    occurs_ref: Option<String>,
+    inner_occurs: Vec<InnerOccurs>,
+    condition: Option<Condition>,
+    fixed: Option<String>,
+    compute: Option<Compute>,
+    blob: bool,
+    embeds: Option<String>,
+    map: Option<MapType>,
+    availability: Availability
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ElementType {
     Named { name: String, type_: String },
     Ref(String),
     Complex(Option<String>, AnonComplexType)
 }
 
-#[derive(Debug)]
+// unnamed bytes from `<padding bytes>` or `<reserved type>`, written as `fill` and skipped on read
+#[derive(Debug, Clone)]
+pub struct Padding {
+    type_: String,
+    count: Option<u32>,
+    fill: String,
+    availability: Availability
+}
+
+#[derive(Debug, Clone)]
 pub struct AnonComplexType {
     content: ComplexTypeContent,
     doc: Option<String>
 }
 
+impl Availability {
+    // without a requested version only the newest revision, i.e. everything without an `until`, is kept,
+    // without requested profiles everything is kept whatever its profile
+    pub fn includes(&self, version: Option<u32>, profiles: &[String]) -> bool {
+        let version = match version {
+            Some(version) => self.since.unwrap_or(0) <= version && version <= self.until.unwrap_or(u32::MAX),
+            None => self.until.is_none()
+        };
+        let profile = profiles.is_empty() || self.profiles.is_empty()
+            || self.profiles.iter().any(|profile| profiles.contains(profile));
+        version && profile
+    }
+}
+
 impl Packet {
     pub fn new(type_: String) -> Self {
         Packet {
             type_: type_,
             contents: Vec::new(),
-            doc: None
+            doc: None,
+            presence: None,
+            availability: Availability::default(),
+            direction: None,
+            class_name: None,
+            file_name: None
         }
     }
 
@@ -162,9 +290,54 @@ impl Packet {
         self.contents
     }
 
+    pub fn prepend_contents(&mut self, mut contents: Vec<PacketContent>) {
+        contents.append(&mut self.contents);
+        self.contents = contents;
+    }
+
     pub fn set_doc(&mut self, doc: String) {
         self.doc = Some(doc);
     }
+
+    pub fn presence(&self) -> &Option<String> {
+        &self.presence
+    }
+
+    pub fn set_presence(&mut self, presence: String) {
+        self.presence = Some(presence);
+    }
+
+    pub fn availability(&self) -> &Availability {
+        &self.availability
+    }
+
+    pub fn set_availability(&mut self, availability: Availability) {
+        self.availability = availability;
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.direction = Some(direction);
+    }
+
+    pub fn class_name(&self) -> &Option<String> {
+        &self.class_name
+    }
+
+    pub fn set_class_name(&mut self, class_name: String) {
+        self.class_name = Some(class_name);
+    }
+
+    pub fn file_name(&self) -> &Option<String> {
+        &self.file_name
+    }
+
+    pub fn set_file_name(&mut self, file_name: String) {
+        self.file_name = Some(file_name);
+    }
 }
 
 impl ComplexType {
@@ -172,10 +345,19 @@ impl ComplexType {
         ComplexType {
             name: name,
             content: content,
-            doc: None
+            doc: None,
+            availability: Availability::default()
         }
     }
 
+    pub fn availability(&self) -> &Availability {
+        &self.availability
+    }
+
+    pub fn set_availability(&mut self, availability: Availability) {
+        self.availability = availability;
+    }
+
     pub fn name(&self) -> &String {
         &self.name
     }
@@ -221,7 +403,9 @@ impl Sequence {
             occurs: occurs,
             size_occurs: size_occurs,
             doc: doc,
-            inline
+            inline,
+            presence: None,
+            condition: None
         }
     }
 
@@ -256,6 +440,22 @@ impl Sequence {
     pub fn set_inline(&mut self, inline: bool) {
         self.inline = inline;
     }
+
+    pub fn presence(&self) -> &Option<String> {
+        &self.presence
+    }
+
+    pub fn set_presence(&mut self, presence: String) {
+        self.presence = Some(presence);
+    }
+
+    pub fn condition(&self) -> &Option<Condition> {
+        &self.condition
+    }
+
+    pub fn set_condition(&mut self, condition: Condition) {
+        self.condition = Some(condition);
+    }
 }
 
 impl Choice {
@@ -305,7 +505,17 @@ impl Element {
             reference: reference,
             special_read_write,
             enum_type,
-            bits
+            bits,
+            optional: false,
+            occurs_ref: None,
+            inner_occurs: Vec::new(),
+            condition: None,
+            fixed: None,
+            compute: None,
+            blob: false,
+            embeds: None,
+            map: None,
+            availability: Availability::default()
         }
     }
 
@@ -360,6 +570,117 @@ impl Element {
     pub fn set_bits(&mut self, bits: u32) {
         self.bits = Some(bits);
     }
+
+    pub fn optional(&self) -> bool {
+        self.optional
+    }
+
+    pub fn set_optional(&mut self, optional: bool) {
+        self.optional = optional;
+    }
+
+    pub fn occurs_ref(&self) -> &Option<String> {
+        &self.occurs_ref
+    }
+
+    pub fn set_occurs_ref(&mut self, occurs_ref: String) {
+        self.occurs_ref = Some(occurs_ref);
+    }
+
+    pub fn inner_occurs(&self) -> &[InnerOccurs] {
+        &self.inner_occurs
+    }
+
+    pub fn add_inner_occurs(&mut self, inner: InnerOccurs) {
+        self.inner_occurs.push(inner);
+    }
+
+    pub fn condition(&self) -> &Option<Condition> {
+        &self.condition
+    }
+
+    pub fn set_condition(&mut self, condition: Condition) {
+        self.condition = Some(condition);
+    }
+
+    pub fn fixed(&self) -> &Option<String> {
+        &self.fixed
+    }
+
+    pub fn set_fixed(&mut self, fixed: String) {
+        self.fixed = Some(fixed);
+    }
+
+    pub fn compute(&self) -> &Option<Compute> {
+        &self.compute
+    }
+
+    pub fn set_compute(&mut self, compute: Compute) {
+        self.compute = Some(compute);
+    }
+
+    pub fn blob(&self) -> bool {
+        self.blob
+    }
+
+    pub fn set_blob(&mut self, blob: bool) {
+        self.blob = blob;
+    }
+
+    pub fn embeds(&self) -> &Option<String> {
+        &self.embeds
+    }
+
+    pub fn set_embeds(&mut self, embeds: String) {
+        self.embeds = Some(embeds);
+    }
+
+    pub fn map(&self) -> &Option<MapType> {
+        &self.map
+    }
+
+    pub fn set_map(&mut self, map: MapType) {
+        self.map = Some(map);
+    }
+
+    pub fn availability(&self) -> &Availability {
+        &self.availability
+    }
+
+    pub fn set_availability(&mut self, availability: Availability) {
+        self.availability = availability;
+    }
+}
+
+impl Padding {
+    pub fn new(type_: String, count: Option<u32>, fill: String) -> Self {
+        Padding {
+            type_,
+            count,
+            fill,
+            availability: Availability::default()
+        }
+    }
+
+    pub fn availability(&self) -> &Availability {
+        &self.availability
+    }
+
+    pub fn set_availability(&mut self, availability: Availability) {
+        self.availability = availability;
+    }
+
+    pub fn type_(&self) -> &String {
+        &self.type_
+    }
+
+    pub fn count(&self) -> Option<u32> {
+        self.count
+    }
+
+    pub fn fill(&self) -> &String {
+        &self.fill
+    }
 }
 
 impl SimpleType {
@@ -367,10 +688,19 @@ impl SimpleType {
         SimpleType {
             name: name,
             contents: Vec::new(),
-            doc: None
+            doc: None,
+            availability: Availability::default()
         }
     }
 
+    pub fn availability(&self) -> &Availability {
+        &self.availability
+    }
+
+    pub fn set_availability(&mut self, availability: Availability) {
+        self.availability = availability;
+    }
+
     pub fn add_content(&mut self, content: SimpleTypeContent) {
         self.contents.push(content);
     }
@@ -397,7 +727,9 @@ impl Restriction {
         Restriction {
             base: base,
             contents: Vec::new(),
-            doc: None
+            doc: None,
+            flags: false,
+            scale: None
         }
     }
 
@@ -420,6 +752,22 @@ impl Restriction {
     pub fn set_doc(&mut self, doc: String) {
         self.doc = Some(doc);
     }
+
+    pub fn flags(&self) -> bool {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: bool) {
+        self.flags = flags;
+    }
+
+    pub fn scale(&self) -> &Option<Scale> {
+        &self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = Some(scale);
+    }
 }
 
 impl Enumeration {
@@ -427,7 +775,8 @@ impl Enumeration {
         Enumeration {
             value: value,
             id: id,
-            doc: doc
+            doc: doc,
+            bit: None
         }
     }
 
@@ -442,4 +791,11 @@ impl Enumeration {
     pub fn doc(&self) -> &Option<String> {
         &self.doc
     }
+    pub fn bit(&self) -> Option<u32> {
+        self.bit
+    }
+
+    pub fn set_bit(&mut self, bit: u32) {
+        self.bit = Some(bit);
+    }
 }
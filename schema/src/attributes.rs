@@ -207,6 +207,70 @@ impl Parse for ::ast::Occurs {
     }
 }
 
+impl Parse for Vec<::ast::Occurs> {
+    fn parse(val: &str) -> Result<Self> {
+        val.split(',').map(|occurs| ::ast::Occurs::parse(occurs.trim())).collect()
+    }
+}
+
+impl Parse for ::ast::Condition {
+    fn parse(val: &str) -> Result<Self> {
+        use ::ast::{Condition, ConditionOp};
+        let (idx, op, len) = if let Some(idx) = val.find("==") {
+            (idx, ConditionOp::Eq, 2)
+        } else if let Some(idx) = val.find("!=") {
+            (idx, ConditionOp::Ne, 2)
+        } else if let Some(idx) = val.find('&') {
+            (idx, ConditionOp::Mask, 1)
+        } else {
+            return Err(ParseError::new(format!("Invalid condition {}", val)));
+        };
+        let field = val[..idx].trim();
+        let value = val[idx + len..].trim();
+        if field.is_empty() || value.is_empty() {
+            return Err(ParseError::new(format!("Invalid condition {}", val)));
+        }
+        Ok(Condition { field: field.to_owned(), op, value: value.to_owned() })
+    }
+}
+
+impl Parse for ::ast::Compute {
+    fn parse(val: &str) -> Result<Self> {
+        use ::ast::{Compute, ComputeKind};
+        let (name, range) = match val.find('(') {
+            Some(idx) if val.ends_with(')') => (val[..idx].trim(), Some(&val[idx + 1..val.len() - 1])),
+            Some(_) => return Err(ParseError::new(format!("Invalid compute {}", val))),
+            None => (val.trim(), None)
+        };
+        let kind = match name {
+            "length" => ComputeKind::Length,
+            "crc16" => ComputeKind::Crc16,
+            "sum8" => ComputeKind::Sum8,
+            _ => return Err(ParseError::new(format!("Invalid compute function {}", name)))
+        };
+        let (start, end) = match range.map(|range| range.trim()) {
+            None | Some("") => (None, None),
+            Some(range) => match range.find("..") {
+                Some(idx) => (Some(range[..idx].trim().to_owned()), Some(range[idx + 2..].trim().to_owned())),
+                None => (Some(range.to_owned()), Some(range.to_owned()))
+            }
+        };
+        Ok(Compute { kind, start, end })
+    }
+}
+
+impl Parse for ::ast::Direction {
+    fn parse(val: &str) -> Result<Self> {
+        use ::ast::Direction;
+        match val {
+            "client" => Ok(Direction::Client),
+            "server" => Ok(Direction::Server),
+            "inter" => Ok(Direction::Inter),
+            _ => Err(ParseError::new(format!("Invalid direction {}", val)))
+        }
+    }
+}
+
 impl Parse for String {
     fn parse(val: &str) -> Result<Self> {
         Ok(val.to_string())
@@ -41,6 +41,13 @@ pub fn parse_include_xml(r: &mut Reader, attrs: Attributes) -> Result<Packet> {
     Ok(packet)
 }
 
+pub fn parse_import(r: &mut Reader, attrs: Attributes) -> Result<Packet> {
+    trace!("reading import in root");
+    let mut packet = Packet::new("tmp".to_string());
+    packet.add_content(import(r, attrs)?);
+    Ok(packet)
+}
+
 #[derive(Debug)]
 enum Either<A: Debug, B: Debug> {
     A(A),
@@ -51,13 +58,31 @@ fn packet(r: &mut Reader, attrs: Attributes) -> Result<Packet> {
     trace!("reading packet");
     let type_ = attrs.get("ePacketType")?;
     let mut packet = Packet::new(type_);
+    if let Some(presence) = attrs.get_opt("presence") {
+        packet.set_presence(presence);
+    }
+    packet.set_availability(availability(&attrs)?);
+    if let Some(direction) = attrs.parse_opt("direction")? {
+        packet.set_direction(direction);
+    }
+    if let Some(class_name) = attrs.get_opt("className") {
+        packet.set_class_name(class_name);
+    }
+    if let Some(file_name) = attrs.get_opt("fileName") {
+        packet.set_file_name(file_name);
+    }
 
     use self::PacketContent::*;
     use self::Either::*;
     for item in r.map(&[
         ("includeXml", &|r, attrs| Ok(A(include_xml(r, attrs)?))),
+        ("import", &|r, attrs| Ok(A(import(r, attrs)?))),
         ("include", &|r, attrs| Ok(A(include(r, attrs)?))),
-        ("element", &|r, attrs| Ok(A(Element(element(r, attrs)?)))),
+        ("element", &|r, attrs| Ok(A(Element(Box::new(element(r, attrs)?))))),
+        ("padding", &|r, attrs| Ok(A(Padding(padding(r, attrs)?)))),
+        ("reserved", &|r, attrs| Ok(A(Padding(reserved(r, attrs)?)))),
+        ("bytes", &|r, attrs| Ok(A(Element(Box::new(bytes(r, attrs)?))))),
+        ("map", &|r, attrs| Ok(A(Element(Box::new(map(r, attrs)?))))),
         ("simpleType", &|r, attrs| Ok(A(SimpleType(simple_type(r, attrs)?)))),
         ("complexType", &|r, attrs| Ok(A(ComplexType(complex_type(r, attrs)?)))),
         ("documentation", &|r, attrs| Ok(B(documentation(r, attrs)?)))
@@ -96,8 +121,16 @@ fn seq(r: &mut Reader, attrs: Attributes) -> Result<Sequence> {
     let occurs = attrs.parse_opt("occurs")?;
     let size_occurs = attrs.parse_opt("occursSize")?;
     let inline = attrs.parse_opt("inline")?.unwrap_or(false);
+    let presence = attrs.get_opt("presence");
+    let condition = attrs.parse_opt("if")?;
     let (doc, contents) = seq_or_choice_children(r, attrs)?;
     let mut seq = Sequence::new(occurs, size_occurs, doc, inline);
+    if let Some(presence) = presence {
+        seq.set_presence(presence);
+    }
+    if let Some(condition) = condition {
+        seq.set_condition(condition);
+    }
     for content in contents {
         seq.add_content(content);
     }
@@ -124,9 +157,13 @@ fn seq_or_choice_children(r: &mut Reader, _: Attributes) -> Result<(Option<Strin
     use self::SequenceContent::*;
     trace!("reading sequence/choice content");
     for content in r.map(&[
-        ("element", &|r, attrs| Ok(A(Element(element(r, attrs)?)))),
+        ("element", &|r, attrs| Ok(A(Element(Box::new(element(r, attrs)?))))),
         ("choice", &|r, attrs| Ok(A(Choice(choice(r, attrs)?)))),
         ("sequence", &|r, attrs| Ok(A(Seq(seq(r, attrs)?)))),
+        ("padding", &|r, attrs| Ok(A(Padding(padding(r, attrs)?)))),
+        ("reserved", &|r, attrs| Ok(A(Padding(reserved(r, attrs)?)))),
+        ("bytes", &|r, attrs| Ok(A(Element(Box::new(bytes(r, attrs)?))))),
+        ("map", &|r, attrs| Ok(A(Element(Box::new(map(r, attrs)?))))),
         ("documentation", &|r, attrs| Ok(B(documentation(r, attrs)?)))
     ])? {
         match content {
@@ -145,6 +182,7 @@ fn complex_type(r: &mut Reader, attrs: Attributes) -> Result<ComplexType> {
     if let Some(doc) = doc {
         cot.set_doc(doc);
     }
+    cot.set_availability(availability(&attrs)?);
     Ok(cot)
 }
 
@@ -161,10 +199,18 @@ fn include_xml(_: &mut Reader, attrs: Attributes) -> Result<PacketContent> {
     Ok(PacketContent::IncludeXml(path))
 }
 
+fn import(_: &mut Reader, attrs: Attributes) -> Result<PacketContent> {
+    trace!("reading import");
+    let path = attrs.get("path")?;
+    let alias = attrs.get("as")?;
+    Ok(PacketContent::Import(Import { path, alias }))
+}
+
 fn simple_type(r: &mut Reader, attrs: Attributes) -> Result<SimpleType> {
     trace!("reading simple_type");
     let name = attrs.get("name")?;
     let mut sit = SimpleType::new(name);
+    sit.set_availability(availability(&attrs)?);
 
     use self::Either::*;
     for item in r.map(&[
@@ -183,6 +229,11 @@ fn restriction(r: &mut Reader, attrs: Attributes) -> Result<Restriction> {
     trace!("reading restriction");
     let base = attrs.get::<String>("base")?;
     let mut restrict = Restriction::new(base);
+    restrict.set_flags(attrs.parse_opt("flags")?.unwrap_or(false));
+    if let Some(factor) = attrs.get_opt("scale") {
+        let offset = attrs.get_opt("offset").unwrap_or_else(|| "0".to_owned());
+        restrict.set_scale(Scale { factor, offset });
+    }
 
     use self::Either::*;
     use self::RestrictionContent::*;
@@ -206,12 +257,17 @@ fn enumeration(r: &mut Reader, attrs: Attributes) -> Result<Enumeration> {
     trace!("reading enumeration");
     let value = attrs.get("value")?;
     let id = attrs.parse_opt("id")?;
+    let bit = attrs.parse_opt("bit")?;
     let mut doc = None;
     for documentation in r.map(&[("documentation", &documentation)])? {
         doc = Some(documentation);
     }
 
-    Ok(Enumeration::new(value, id, doc))
+    let mut enumeration = Enumeration::new(value, id, doc);
+    if let Some(bit) = bit {
+        enumeration.set_bit(bit);
+    }
+    Ok(enumeration)
 }
 
 fn element(r: &mut Reader, attrs: Attributes) -> Result<Element> {
@@ -219,12 +275,24 @@ fn element(r: &mut Reader, attrs: Attributes) -> Result<Element> {
     let type_ = attrs.get_opt("type");
     let name = attrs.get_opt("name");
     let default = attrs.get_opt("default");
-    let occurs = attrs.parse_opt("occurs")?;
-    let size_occurs = attrs.parse_opt("occursSize")?;
+    let mut occurs = attrs.parse_opt::<Vec<Occurs>>("occurs")?.unwrap_or_default().into_iter();
+    let mut size_occurs = attrs.get_opt("occursSize").map_or_else(Vec::new, |sizes| {
+        sizes.split(',').map(|size| match size.trim() {
+            "" => None,
+            size => Some(size.to_owned())
+        }).collect()
+    }).into_iter();
+    let (outer_occurs, outer_size_occurs) = (occurs.next(), size_occurs.next().and_then(|size| size));
     let reference = attrs.get_or("ref", false);
     let read_write = attrs.get_opt("readWrite");
     let enum_type = attrs.get_opt("enum");
     let bits = attrs.parse_opt("bits")?;
+    let optional = attrs.parse_opt("optional")?.unwrap_or(false);
+    let occurs_ref = attrs.get_opt("occursRef");
+    let condition = attrs.parse_opt("if")?;
+    let fixed = attrs.get_opt("fixed").or_else(|| attrs.get_opt("const"));
+    let compute = attrs.parse_opt("compute")?;
+    let availability = availability(&attrs)?;
     let mut doc = None;
     let init = match default {
         Some(def) => {
@@ -254,7 +322,24 @@ fn element(r: &mut Reader, attrs: Attributes) -> Result<Element> {
             }
     }
     type_.map(|type_| {
-        let mut elem = Element::new(type_, init, occurs, size_occurs, reference, read_write, enum_type, bits);
+        let mut elem = Element::new(type_, init, outer_occurs, outer_size_occurs, reference, read_write, enum_type, bits);
+        for occurs in occurs {
+            elem.add_inner_occurs(InnerOccurs { occurs, size_occurs: size_occurs.next().and_then(|size| size) });
+        }
+        elem.set_optional(optional);
+        if let Some(ref occurs_ref) = occurs_ref {
+            elem.set_occurs_ref(occurs_ref.clone());
+        }
+        if let Some(condition) = condition {
+            elem.set_condition(condition);
+        }
+        if let Some(fixed) = fixed {
+            elem.set_fixed(fixed);
+        }
+        if let Some(compute) = compute {
+            elem.set_compute(compute);
+        }
+        elem.set_availability(availability.clone());
         if let Some(doc) = doc {
             elem.set_doc(doc);
         }
@@ -262,6 +347,87 @@ fn element(r: &mut Reader, attrs: Attributes) -> Result<Element> {
     }).ok_or_else(|| ParseError::new("name and/or type not found for element"))
 }
 
+fn padding(_: &mut Reader, attrs: Attributes) -> Result<Padding> {
+    trace!("reading padding");
+    let bytes = attrs.get("bytes")?;
+    let fill = attrs.get_or("fill", "0".to_owned());
+    let mut padding = Padding::new("uint8_t".to_owned(), Some(bytes), fill);
+    padding.set_availability(availability(&attrs)?);
+    Ok(padding)
+}
+
+fn reserved(_: &mut Reader, attrs: Attributes) -> Result<Padding> {
+    trace!("reading reserved");
+    let type_ = attrs.get("type")?;
+    let fill = attrs.get_or("fill", "0".to_owned());
+    let mut padding = Padding::new(type_, None, fill);
+    padding.set_availability(availability(&attrs)?);
+    Ok(padding)
+}
+
+// an opaque `<bytes>` blob, either `length` bytes long, prefixed by an `occursSize` or running to the end
+fn bytes(r: &mut Reader, attrs: Attributes) -> Result<Element> {
+    trace!("reading bytes");
+    let name = attrs.get("name")?;
+    let occurs = match attrs.get_opt("length") {
+        Some(length) => Occurs::Num(length),
+        None => Occurs::Unbounded
+    };
+    let size_occurs = attrs.get_opt("occursSize");
+    let condition = attrs.parse_opt("if")?;
+    let embeds = attrs.get_opt("embeds");
+    let type_ = ElementType::Named{ name, type_: "uint8_t".to_owned() };
+    let mut elem = Element::new(type_, ElementInitValue::Create, Some(occurs), size_occurs, false, None, None, None);
+    elem.set_blob(true);
+    elem.set_availability(availability(&attrs)?);
+    if let Some(condition) = condition {
+        elem.set_condition(condition);
+    }
+    if let Some(embeds) = embeds {
+        elem.set_embeds(embeds);
+    }
+    for doc in r.map(&[
+        ("documentation", &|r, attrs| documentation(r, attrs))
+    ])? {
+        elem.set_doc(doc);
+    }
+    Ok(elem)
+}
+
+fn map(r: &mut Reader, attrs: Attributes) -> Result<Element> {
+    trace!("reading map");
+    let name = attrs.get("name")?;
+    let key = attrs.get("keyType")?;
+    let value: String = attrs.get("valueType")?;
+    let size_occurs = attrs.get("occursSize")?;
+    let ordered = attrs.parse_opt("ordered")?.unwrap_or(false);
+    let condition = attrs.parse_opt("if")?;
+    let type_ = ElementType::Named{ name, type_: value.clone() };
+    let mut elem = Element::new(type_, ElementInitValue::Create, None, Some(size_occurs), false, None, None, None);
+    elem.set_map(MapType { key, value, ordered });
+    elem.set_availability(availability(&attrs)?);
+    if let Some(condition) = condition {
+        elem.set_condition(condition);
+    }
+    for doc in r.map(&[
+        ("documentation", &|r, attrs| documentation(r, attrs))
+    ])? {
+        elem.set_doc(doc);
+    }
+    Ok(elem)
+}
+
+fn availability(attrs: &Attributes) -> Result<Availability> {
+    let profiles = attrs.get_opt("profile").map_or_else(Vec::new, |profiles: String| {
+        profiles.split(',').map(|profile| profile.trim().to_owned()).filter(|profile| !profile.is_empty()).collect()
+    });
+    let availability = Availability { since: attrs.parse_opt("since")?, until: attrs.parse_opt("until")?, profiles };
+    match (availability.since, availability.until) {
+        (Some(since), Some(until)) if since > until => Err(ParseError::new(format!("since {} is after until {}", since, until))),
+        _ => Ok(availability)
+    }
+}
+
 fn documentation(r: &mut Reader, _: Attributes) -> Result<String> {
     trace!("reading documentation");
     Ok(r.read_text()?.trim().to_string())
@@ -276,3 +442,255 @@ fn anon_complex_type (r: &mut Reader, _: Attributes) -> Result<AnonComplexType>
     }
     Ok(cot)
 }
+
+#[cfg(test)]
+mod tests {
+    use ::ast::*;
+    use ::reader::Reader;
+
+    fn load(xml: &str) -> Packet {
+        Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).unwrap()
+    }
+
+    fn elements(packet: &Packet) -> Vec<&Element> {
+        packet.contents().iter().filter_map(|content| match content {
+            PacketContent::Element(ref elem) => Some(&**elem),
+            _ => None
+        }).collect()
+    }
+
+    #[test]
+    fn optional_element() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET" presence="uint8_t">
+            <element name="hp" type="uint16_t" optional="true"/>
+            <element name="mp" type="uint16_t"/>
+        </packet>"#);
+        assert_eq!(*packet.presence(), Some("uint8_t".to_owned()));
+        let elements = elements(&packet);
+        assert!(elements[0].optional());
+        assert!(!elements[1].optional());
+    }
+
+    #[test]
+    fn occurs_ref() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="count" type="uint8_t"/>
+            <element name="items" type="uint32_t" occurs="unbounded" occursRef="count"/>
+        </packet>"#);
+        let elements = elements(&packet);
+        assert_eq!(*elements[1].occurs(), Some(Occurs::Unbounded));
+        assert_eq!(*elements[1].occurs_ref(), Some("count".to_owned()));
+    }
+
+    #[test]
+    fn nested_occurs() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="grid" type="uint8_t" occurs="unbounded,4,unbounded" occursSize="uint16_t,,uint8_t"/>
+        </packet>"#);
+        let elements = elements(&packet);
+        assert_eq!(*elements[0].occurs(), Some(Occurs::Unbounded));
+        assert_eq!(*elements[0].size_occurs(), Some("uint16_t".to_owned()));
+        assert_eq!(elements[0].inner_occurs(), &[
+            InnerOccurs { occurs: Occurs::Num("4".to_owned()), size_occurs: None },
+            InnerOccurs { occurs: Occurs::Unbounded, size_occurs: Some("uint8_t".to_owned()) }
+        ]);
+    }
+
+    #[test]
+    fn flags() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <simpleType name="Status">
+                <restriction base="uint8_t" flags="true">
+                    <enumeration value="A" bit="3"/>
+                </restriction>
+            </simpleType>
+        </packet>"#);
+        let restriction = match packet.contents()[0] {
+            PacketContent::SimpleType(ref simple) => match simple.contents()[0] {
+                SimpleTypeContent::Restriction(ref restriction) => restriction.clone()
+            },
+            _ => panic!("expected a simple type")
+        };
+        assert!(restriction.flags());
+        match restriction.contents()[0] {
+            RestrictionContent::Enumeration(ref e) => assert_eq!(e.bit(), Some(3)),
+            _ => panic!("expected an enumeration")
+        }
+    }
+
+    #[test]
+    fn conditions() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="kind" type="uint8_t"/>
+            <element name="a" type="uint8_t" if="kind == 1"/>
+            <element name="b" type="uint8_t" if="kind!=2"/>
+            <element name="c" type="uint8_t" if="kind &amp; 0x4"/>
+        </packet>"#);
+        let conditions = elements(&packet).iter().filter_map(|elem| elem.condition().clone()).collect::<Vec<_>>();
+        assert_eq!(conditions, vec![
+            Condition { field: "kind".to_owned(), op: ConditionOp::Eq, value: "1".to_owned() },
+            Condition { field: "kind".to_owned(), op: ConditionOp::Ne, value: "2".to_owned() },
+            Condition { field: "kind".to_owned(), op: ConditionOp::Mask, value: "0x4".to_owned() }
+        ]);
+    }
+
+    #[test]
+    fn invalid_condition() {
+        let xml = r#"<packet ePacketType="PAKCS_PACKET"><element name="a" type="uint8_t" if="kind"/></packet>"#;
+        assert!(Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).is_err());
+    }
+
+    #[test]
+    fn fixed() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="magic" type="uint32_t" fixed="0xCAFE"/>
+            <element name="version" type="uint8_t" const="2"/>
+        </packet>"#);
+        let elements = elements(&packet);
+        assert_eq!(*elements[0].fixed(), Some("0xCAFE".to_owned()));
+        assert_eq!(*elements[1].fixed(), Some("2".to_owned()));
+    }
+
+    #[test]
+    fn computes() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="size" type="uint16_t" compute="length(body)"/>
+            <element name="body" type="uint8_t"/>
+            <element name="crc" type="uint16_t" compute="crc16(size..body)"/>
+            <element name="sum" type="uint8_t" compute="sum8"/>
+        </packet>"#);
+        let computes = elements(&packet).iter().filter_map(|elem| elem.compute().clone()).collect::<Vec<_>>();
+        assert_eq!(computes, vec![
+            Compute { kind: ComputeKind::Length, start: Some("body".to_owned()), end: Some("body".to_owned()) },
+            Compute { kind: ComputeKind::Crc16, start: Some("size".to_owned()), end: Some("body".to_owned()) },
+            Compute { kind: ComputeKind::Sum8, start: None, end: None }
+        ]);
+    }
+
+    #[test]
+    fn invalid_compute() {
+        let xml = r#"<packet ePacketType="PAKCS_PACKET"><element name="a" type="uint8_t" compute="md5(b)"/></packet>"#;
+        assert!(Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).is_err());
+    }
+
+    #[test]
+    fn scale() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <simpleType name="Speed">
+                <restriction base="uint16_t" scale="0.01" offset="-10"/>
+            </simpleType>
+        </packet>"#);
+        match packet.contents()[0] {
+            PacketContent::SimpleType(ref simple) => match simple.contents()[0] {
+                SimpleTypeContent::Restriction(ref restriction) => assert_eq!(*restriction.scale(), Some(Scale { factor: "0.01".to_owned(), offset: "-10".to_owned() }))
+            },
+            _ => panic!("expected a simple type")
+        }
+    }
+
+    #[test]
+    fn paddings() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <padding bytes="4" fill="0xFF"/>
+            <reserved type="uint16_t"/>
+        </packet>"#);
+        let paddings = packet.contents().iter().filter_map(|content| match content {
+            PacketContent::Padding(ref padding) => Some((padding.type_().clone(), padding.count(), padding.fill().clone())),
+            _ => None
+        }).collect::<Vec<_>>();
+        assert_eq!(paddings, vec![
+            ("uint8_t".to_owned(), Some(4), "0xFF".to_owned()),
+            ("uint16_t".to_owned(), None, "0".to_owned())
+        ]);
+    }
+
+    #[test]
+    fn invalid_padding() {
+        let xml = r#"<packet ePacketType="PAKCS_PACKET"><padding bytes="four"/></packet>"#;
+        assert!(Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).is_err());
+        let xml = r#"<packet ePacketType="PAKCS_PACKET"><reserved fill="1"/></packet>"#;
+        assert!(Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).is_err());
+    }
+
+    #[test]
+    fn bytes() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <bytes name="key" length="16"/>
+            <bytes name="inner" occursSize="uint16_t" embeds="PAKCS_INNER"/>
+            <bytes name="rest"/>
+        </packet>"#);
+        let elements = elements(&packet);
+        assert!(elements.iter().all(|elem| elem.blob()));
+        assert_eq!(*elements[0].occurs(), Some(Occurs::Num("16".to_owned())));
+        assert_eq!(*elements[1].size_occurs(), Some("uint16_t".to_owned()));
+        assert_eq!(*elements[1].embeds(), Some("PAKCS_INNER".to_owned()));
+        assert_eq!(*elements[2].occurs(), Some(Occurs::Unbounded));
+    }
+
+    #[test]
+    fn map() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <map name="items" keyType="uint16_t" valueType="uint32_t" occursSize="uint8_t" ordered="true"/>
+        </packet>"#);
+        let elements = elements(&packet);
+        assert_eq!(*elements[0].map(), Some(MapType { key: "uint16_t".to_owned(), value: "uint32_t".to_owned(), ordered: true }));
+        assert_eq!(*elements[0].size_occurs(), Some("uint8_t".to_owned()));
+    }
+
+    #[test]
+    fn versions() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET" since="2">
+            <element name="old" type="uint8_t" until="3"/>
+            <element name="new" type="uint16_t" since="4"/>
+        </packet>"#);
+        assert_eq!(*packet.availability(), Availability { since: Some(2), until: None, profiles: Vec::new() });
+        let elements = elements(&packet);
+        assert!(elements[0].availability().includes(Some(3), &[]));
+        assert!(!elements[0].availability().includes(Some(4), &[]));
+        assert!(!elements[0].availability().includes(None, &[]));
+        assert!(!elements[1].availability().includes(Some(3), &[]));
+        assert!(elements[1].availability().includes(None, &[]));
+    }
+
+    #[test]
+    fn since_after_until() {
+        let xml = r#"<packet ePacketType="PAKCS_PACKET"><element name="a" type="uint8_t" since="3" until="2"/></packet>"#;
+        assert!(Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).is_err());
+    }
+
+    #[test]
+    fn profiles() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <element name="debug" type="uint8_t" profile="server, tools"/>
+            <element name="always" type="uint8_t"/>
+        </packet>"#);
+        let elements = elements(&packet);
+        assert_eq!(elements[0].availability().profiles, vec!["server".to_owned(), "tools".to_owned()]);
+        assert!(elements[0].availability().includes(None, &["tools".to_owned()]));
+        assert!(!elements[0].availability().includes(None, &["client".to_owned()]));
+        assert!(elements[0].availability().includes(None, &[]));
+        assert!(elements[1].availability().includes(None, &["client".to_owned()]));
+    }
+
+    #[test]
+    fn packet_naming() {
+        let packet = load(r#"<packet ePacketType="PAKCS_LOGIN_REQ" direction="client" className="LoginRequest" fileName="login"/>"#);
+        assert_eq!(packet.direction(), Some(Direction::Client));
+        assert_eq!(*packet.class_name(), Some("LoginRequest".to_owned()));
+        assert_eq!(*packet.file_name(), Some("login".to_owned()));
+        let xml = r#"<packet ePacketType="PAKCS_LOGIN_REQ" direction="up"/>"#;
+        assert!(Reader::load_packet(::std::io::Cursor::new(xml.to_owned())).is_err());
+    }
+
+    #[test]
+    fn import() {
+        let packet = load(r#"<packet ePacketType="PAKCS_PACKET">
+            <import path="lib/common.xml" as="c"/>
+            <element name="hp" type="c::Hp"/>
+        </packet>"#);
+        match packet.contents()[0] {
+            PacketContent::Import(ref import) => assert_eq!(*import, Import { path: "lib/common.xml".to_owned(), alias: "c".to_owned() }),
+            _ => panic!("expected an import")
+        }
+    }
+}
@@ -17,6 +17,12 @@ impl Reader {
         reader.read()
     }
     
+    // every packet of a file, each one also holding the file-level definitions shared between them
+    pub fn load_packets<R: Read+ 'static>(r: R) -> Result<Vec<Packet>, ::failure::Error> {
+        let mut reader = Reader::new(Box::new(r));
+        reader.read_all()
+    }
+
     pub fn new(source: Box<dyn Read>) -> Self {
         let reader = EventReader::new(source);
         Reader{ reader, path: Vec::new(), event: None }
@@ -37,7 +43,8 @@ impl Reader {
                                 ("simpleType", &::parse::parse_simple_type),
                                 ("complexType", &::parse::parse_complex_type),
                                 ("include", &::parse::parse_include),
-                                ("includeXml", &::parse::parse_include_xml)
+                                ("includeXml", &::parse::parse_include_xml),
+                                ("import", &::parse::parse_import)
             ])? {
                 if item.type_() != "tmp" {
                     return Ok(item);
@@ -51,6 +58,32 @@ impl Reader {
         Err(ParseError::new("Expecting startDocument").into())
     }
 
+    fn read_all(&mut self) -> Result<Vec<Packet>, ::failure::Error> {
+        if let XmlEvent::StartDocument{..} = self.next()? {
+            let mut shared = Vec::new();
+            let mut packets = Vec::new();
+            for item in self.map(&[
+                                ("packet", &::parse::parse_packet),
+                                ("simpleType", &::parse::parse_simple_type),
+                                ("complexType", &::parse::parse_complex_type),
+                                ("include", &::parse::parse_include),
+                                ("includeXml", &::parse::parse_include_xml),
+                                ("import", &::parse::parse_import)
+            ])? {
+                if item.type_() != "tmp" {
+                    packets.push(item);
+                } else {
+                    shared.extend(item.into_contents());
+                }
+            }
+            for packet in packets.iter_mut() {
+                packet.prepend_contents(shared.clone());
+            }
+            return Ok(packets);
+        }
+        Err(ParseError::new("Expecting startDocument").into())
+    }
+
     fn read_node<Out: Debug>(
         &mut self,
         alts: &[(&'static str, & dyn Fn( &mut Reader, Attributes) -> Result<Out, ParseError> )]
@@ -172,3 +205,39 @@ impl Reader {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use ::ast::PacketContent;
+    use super::Reader;
+
+    #[test]
+    fn shared_definitions() {
+        let xml = r#"<simpleType name="Hp"><restriction base="uint16_t"/></simpleType>
+            <packet ePacketType="PAKCS_A"><element name="hp" type="Hp"/></packet>
+            <packet ePacketType="PAKWC_B"><element name="value" type="Hp"/></packet>"#;
+        let packets = Reader::load_packets(::std::io::Cursor::new(xml.to_owned())).unwrap();
+        assert_eq!(packets.iter().map(|packet| packet.type_().as_str()).collect::<Vec<_>>(), vec!["PAKCS_A", "PAKWC_B"]);
+        for packet in &packets {
+            match packet.contents()[0] {
+                PacketContent::SimpleType(ref simple) => assert_eq!(simple.name(), "Hp"),
+                ref content => panic!("expected the shared type first, got {:?}", content)
+            }
+            assert_eq!(packet.contents().len(), 2);
+        }
+    }
+
+    #[test]
+    fn single_packet() {
+        let xml = r#"<packet ePacketType="PAKCS_A"><element name="hp" type="uint16_t"/></packet>"#;
+        let packets = Reader::load_packets(::std::io::Cursor::new(xml.to_owned())).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(packets[0].type_(), "PAKCS_A");
+    }
+
+    #[test]
+    fn include_only() {
+        let xml = r#"<simpleType name="Hp"><restriction base="uint16_t"/></simpleType>"#;
+        assert!(Reader::load_packets(::std::io::Cursor::new(xml.to_owned())).unwrap().is_empty());
+    }
+}